@@ -0,0 +1,22 @@
+use std::path::Path;
+use std::{env, fs};
+
+/// Lists every structure in `assets/structures`, so adding one is just adding its file.
+fn main() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("assets/structures");
+    println!("cargo:rerun-if-changed={}", dir.display());
+
+    let mut files: Vec<_> = fs::read_dir(&dir)
+        .expect("Couldn't read assets/structures")
+        .map(|entry| entry.expect("Couldn't read assets/structures").path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "ron"))
+        .collect();
+    // The first structure to cover a tile wins, so keep them in an order that doesn't depend on the file system
+    files.sort();
+
+    let list = files.iter()
+        .map(|path| format!("    include_str!({:?}),\n", path.display().to_string()))
+        .collect::<String>();
+    let out = Path::new(&env::var("OUT_DIR").unwrap()).join("structures.rs");
+    fs::write(out, format!("[\n{list}]\n")).expect("Couldn't write the structure list");
+}
@@ -1,57 +1,249 @@
-use bevy_ecs::{schedule::{IntoSystemConfigs, Schedule}, world::World};
-use macroquad::window::next_frame;
+use std::cell::RefCell;
+use std::collections::HashMap;
 
+use bevy_ecs::{component::Component, event::{event_update_condition, event_update_system, Event, EventRegistry, Events, ShouldUpdateEvents}, schedule::{IntoSystemConfigs, IntoSystemSetConfigs, Schedule, SystemSet}, system::{Res, Resource}, world::{FromWorld, World}};
+use macroquad::{input::{is_quit_requested, prevent_quit}, time::get_frame_time, window::next_frame};
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::events::{ChunkFocused, ChunkGenerated, TileChanged, WorldSaved};
+use crate::save::scene::SaveRegistry;
+use crate::state::{GameState, State};
+use crate::time::Time;
 
 pub enum ScheduleLabel_ {
     Startup,
     Update,
     PreUpdate,
-    PostUpdate
+    PostUpdate,
+    /// Runs zero or more times per frame, once per elapsed [`Time::fixed_delta`].
+    FixedUpdate,
+    /// Runs once when [`State`] changes to this state.
+    OnEnter(GameState),
+    /// Runs once when [`State`] changes away from this state.
+    OnExit(GameState),
+    /// Runs when there might not be another frame: the window's being closed, or the browser tab is
+    /// being hidden (which stops frames until it's back) or closed.
+    Suspend,
+}
+
+/// Where systems go in the frame, so plugins can order themselves against each other
+/// without knowing which systems are in there.
+#[derive(SystemSet, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum GameSet {
+    /// Reading devices in `PreUpdate`, and turning that into intent in `FixedUpdate`.
+    Input,
+    /// `FixedUpdate` changes to the world, other than moving things.
+    Simulation,
+    /// `FixedUpdate` movement and collision.
+    Physics,
+    /// Pointing the camera in `Update`, before anything draws with it.
+    Camera,
+    /// Drawing the world in `Update`. Skipped when headless.
+    RenderWorld,
+    /// Drawing over the world in `Update`. Skipped when headless.
+    RenderUi,
+}
+
+/// Present when the [`App`] has no window. Rendering systems are skipped and
+/// input has to be written to [`crate::input::InputActions`] by hand.
+#[derive(Resource)]
+pub struct Headless;
+
+/// Run condition for systems that need a window, i.e. anything that draws.
+pub fn windowed(headless: Option<Res<Headless>>) -> bool {
+    headless.is_none()
 }
 
+/// Insert this to close the game at the end of the frame.
+#[derive(Resource)]
+pub struct AppExit;
+
 #[derive(Default)]
 pub struct App {
+    /// Clears out old events before anything else runs each frame.
+    events_schedule: Schedule,
     startup_schedule: Schedule,
     update_schedule: Schedule,
     preupdate_schedule: Schedule,
     postupdate_schedule: Schedule,
+    fixed_update_schedule: Schedule,
+    enter_schedules: HashMap<GameState, Schedule>,
+    exit_schedules: HashMap<GameState, Schedule>,
+    suspend_schedule: Schedule,
     pub world: World
 }
 
+thread_local! {
+    /// The app being run, kept where the browser can get at it between frames.
+    static RUNNING: RefCell<Option<App>> = const { RefCell::new(None) };
+}
+
+/// Called by the page when it's hidden or closed, see `wasm-build.sh`.
+#[cfg(target_family = "wasm")]
+#[no_mangle]
+pub extern "C" fn suspend_game() {
+    RUNNING.with(|app| {
+        // Only ever borrowed during a frame, which the page can't interrupt
+        if let Ok(mut app) = app.try_borrow_mut() {
+            if let Some(app) = app.as_mut() {
+                app.suspend();
+            }
+        }
+    });
+}
+
 impl App {
     pub fn new() -> Self {
-        App {
-            world: World::new(),
+        let mut world = World::new();
+        world.init_resource::<Time>();
+        world.init_resource::<State>();
+        world.init_resource::<SaveRegistry>();
+        
+        let mut events_schedule = Schedule::default();
+        events_schedule.add_systems(event_update_system.run_if(event_update_condition));
+        
+        let mut app = App {
+            events_schedule,
+            world,
             ..Default::default()
-        }
+        };
+        app
+            .configure_sets(ScheduleLabel_::PreUpdate, GameSet::Input)
+            .configure_sets(ScheduleLabel_::FixedUpdate, (GameSet::Input, GameSet::Simulation, GameSet::Physics).chain())
+            .configure_sets(ScheduleLabel_::Update, (GameSet::Camera, GameSet::RenderWorld, GameSet::RenderUi).chain())
+            .configure_sets(ScheduleLabel_::Update, (GameSet::RenderWorld, GameSet::RenderUi).run_if(windowed))
+            .add_event::<TileChanged>()
+            .add_event::<ChunkGenerated>()
+            .add_event::<ChunkFocused>()
+            .add_event::<WorldSaved>();
+        app
+    }
+    /// An [`App`] that never touches macroquad's window or clock, driven with [`App::step`].
+    pub fn headless() -> Self {
+        let mut app = App::new();
+        app.world.insert_resource(Headless);
+        app
     }
-    pub async fn run(&mut self) -> ! {
+    pub async fn run(mut self) {
+        // Closing the window asks first, so there's a chance to save
+        prevent_quit();
         self.startup();
+        RUNNING.set(Some(self));
         loop {
-            self.update();
+            let exit = RUNNING.with_borrow_mut(|app| {
+                let app = app.as_mut().unwrap();
+                app.update();
+                if is_quit_requested() {
+                    app.suspend();
+                    app.world.insert_resource(AppExit);
+                }
+                app.world.contains_resource::<AppExit>()
+            });
+            if exit {
+                break;
+            }
             next_frame().await;
         }
+        RUNNING.set(None);
+    }
+    /// Runs [`ScheduleLabel_::Suspend`].
+    pub fn suspend(&mut self) {
+        self.suspend_schedule.run(&mut self.world);
     }
     pub fn startup(&mut self) {
         self.startup_schedule.run(&mut self.world);
+        
+        let state = self.world.resource::<State>().get();
+        if let Some(schedule) = self.enter_schedules.get_mut(&state) {
+            schedule.run(&mut self.world);
+        }
+        self.apply_state_transitions();
     }
     pub fn update(&mut self) {
+        self.update_with_delta(get_frame_time());
+    }
+    /// Runs `frames` frames of `delta` seconds each, without waiting on a window.
+    pub fn step(&mut self, frames: u32, delta: f32) {
+        for _ in 0..frames {
+            self.update_with_delta(delta);
+        }
+    }
+    fn update_with_delta(&mut self, delta: f32) {
+        self.world.resource_mut::<Time>().advance(delta);
+        
+        self.events_schedule.run(&mut self.world);
         self.preupdate_schedule.run(&mut self.world);
+        self.apply_state_transitions();
+        while self.world.resource_mut::<Time>().expend() {
+            self.fixed_update_schedule.run(&mut self.world);
+            // Events sent during a tick have to last until the next tick, however many frames away
+            self.world.resource_mut::<EventRegistry>().should_update = ShouldUpdateEvents::Ready;
+        }
         self.update_schedule.run(&mut self.world);
         self.postupdate_schedule.run(&mut self.world);
     }
+    /// Entering a state can ask for another (loading finishing straight away), so keep
+    /// going until nothing else is queued.
+    fn apply_state_transitions(&mut self) {
+        while let Some((exited, entered)) = self.world.resource_mut::<State>().transition() {
+            if exited == entered {
+                continue;
+            }
+            if let Some(schedule) = self.exit_schedules.get_mut(&exited) {
+                schedule.run(&mut self.world);
+            }
+            if let Some(schedule) = self.enter_schedules.get_mut(&entered) {
+                schedule.run(&mut self.world);
+            }
+        }
+    }
     pub fn add_systems<M>(
         &mut self,
         schedule: ScheduleLabel_,
         systems: impl IntoSystemConfigs<M>,
     ) -> &mut Self {
+        self.schedule_mut(schedule).add_systems(systems);
+        self
+    }
+    pub fn configure_sets(
+        &mut self,
+        schedule: ScheduleLabel_,
+        sets: impl IntoSystemSetConfigs,
+    ) -> &mut Self {
+        self.schedule_mut(schedule).configure_sets(sets);
+        self
+    }
+    fn schedule_mut(&mut self, schedule: ScheduleLabel_) -> &mut Schedule {
         match schedule {
-            ScheduleLabel_::Startup => self.startup_schedule.add_systems(systems), 
-            ScheduleLabel_::Update => self.update_schedule.add_systems(systems),
-            ScheduleLabel_::PreUpdate => self.preupdate_schedule.add_systems(systems),
-            ScheduleLabel_::PostUpdate => self.postupdate_schedule.add_systems(systems),
-        };
-        
+            ScheduleLabel_::Startup => &mut self.startup_schedule, 
+            ScheduleLabel_::Update => &mut self.update_schedule,
+            ScheduleLabel_::PreUpdate => &mut self.preupdate_schedule,
+            ScheduleLabel_::PostUpdate => &mut self.postupdate_schedule,
+            ScheduleLabel_::FixedUpdate => &mut self.fixed_update_schedule,
+            ScheduleLabel_::OnEnter(state) => self.enter_schedules.entry(state).or_default(),
+            ScheduleLabel_::OnExit(state) => self.exit_schedules.entry(state).or_default(),
+            ScheduleLabel_::Suspend => &mut self.suspend_schedule,
+        }
+    }
+    /// Sets up [`Events`] of type `E`, dropping each one after it's had a tick and a frame to be read.
+    pub fn add_event<E: Event>(&mut self) -> &mut Self {
+        if !self.world.contains_resource::<Events<E>>() {
+            EventRegistry::register_event::<E>(&mut self.world);
+            self.world.resource_mut::<EventRegistry>().should_update = ShouldUpdateEvents::Waiting;
+        }
+        self
+    }
+    /// Saves `C` on [`crate::save::scene::Saveable`] entities under `name`. See [`SaveRegistry::register`].
+    pub fn register_saveable<C: Component + Serialize + DeserializeOwned>(&mut self, name: &'static str) -> &mut Self {
+        self.world.resource_mut::<SaveRegistry>().register::<C>(name);
+        self
+    }
+    pub fn init_resource<R: Resource + FromWorld>(&mut self) -> &mut Self {
+        self.world.init_resource::<R>();
+        self
+    }
+    pub fn insert_resource<R: Resource>(&mut self, resource: R) -> &mut Self {
+        self.world.insert_resource(resource);
         self
     }
     pub fn add_plugin(
@@ -1,35 +1,44 @@
 use macroquad::prelude::*;
 use bevy_ecs::prelude::*;
+use crate::app::Headless;
 use crate::physics2::Collider;
-use crate::position::ScreenPos;
+use crate::position::{ScreenPos, WorldPos};
+use crate::time::Time;
 use crate::VIRTUAL_HEIGHT;
 use crate::VIRTUAL_WIDTH;
 
 use super::player::PlayerTag;
+use super::tile_map::ChunkMap;
 
 #[derive(Component)]
 pub struct GameCamera(pub Camera2D);
 
-pub(super) fn init_camera(mut commands: Commands) {
-    let render_target = render_target(VIRTUAL_WIDTH as u32, VIRTUAL_HEIGHT as u32);
-    render_target.texture.set_filter(FilterMode::Nearest);
+pub(super) fn init_camera(mut commands: Commands, headless: Option<Res<Headless>>) {
     let mut render_target_cam =
         Camera2D::from_display_rect(Rect::new(0., 0., VIRTUAL_WIDTH, VIRTUAL_HEIGHT));
-    render_target_cam.render_target = Some(render_target);
+    
+    // Render targets need a GL context, a headless camera only tracks its target
+    if headless.is_none() {
+        let render_target = render_target(VIRTUAL_WIDTH as u32, VIRTUAL_HEIGHT as u32);
+        render_target.texture.set_filter(FilterMode::Nearest);
+        render_target_cam.render_target = Some(render_target);
+    }
     commands.spawn(GameCamera(render_target_cam));
 }
 
-pub fn setup_camera(camera: Query<&GameCamera>) {
+pub fn setup_camera(camera: Query<&GameCamera>, map: Query<&ChunkMap>) {
     let camera = camera.single();
     set_camera(&camera.0);
     // set_default_camera();
-    clear_background(LIGHTGRAY);
+    let background = map.get_single()
+        .map_or(LIGHTGRAY, |map| map.biome_at(WorldPos(camera.0.target)).background());
+    clear_background(background);
 }
 
-pub(super) fn refocus_camera(mut camera: Query<&mut GameCamera>, player: Query<&Collider, With<PlayerTag>>) {
+pub(super) fn refocus_camera(mut camera: Query<&mut GameCamera>, player: Query<&Collider, With<PlayerTag>>, time: Res<Time>) {
     let mut camera = camera.single_mut();
     let player = player.single();
-    camera.0.target = player.pos;
+    camera.0.target = player.interpolated_pos(time.alpha());
 }
 
 pub fn letterbox_camera(camera: Query<&GameCamera>) {
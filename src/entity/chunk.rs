@@ -0,0 +1,291 @@
+use macroquad::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::entity::ui::draw_tile;
+use crate::grid::Grid;
+use crate::physics2::CollisionResult;
+use crate::tile::TileId;
+
+/// One screen's worth of tiles.
+///
+/// Most chunks are one tile all over (air, or solid rock), so those are stored as just that tile.
+/// The rest are stored as a palette of the tiles in them and as few bits per tile as it takes to
+/// pick one out of it. Saved chunks are run-length encoded on top of that.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(into = "SavedChunk", try_from = "SavedChunk")]
+pub struct Chunk {
+    width: u32,
+    height: u32,
+    tiles: Tiles,
+}
+
+#[derive(Clone, Debug)]
+enum Tiles {
+    Single(TileId),
+    /// Each tile is an index into `palette`, `bits` bits each, packed into `words`.
+    /// `bits` always divides 64, so no tile is split between two words.
+    Packed { palette: Vec<TileId>, bits: u32, words: Vec<u64> },
+}
+
+impl Chunk {
+    /// A chunk of `size` built out of `tile_at`, which is given positions within the chunk.
+    pub fn from_fn(size: UVec2, tile_at: impl Fn(UVec2) -> TileId) -> Chunk {
+        let tiles = (0..size.y).flat_map(|y| (0..size.x).map(move |x| uvec2(x, y))).map(tile_at);
+        Chunk::from_tiles(size, tiles)
+    }
+
+    /// A chunk of `size` out of its tiles row by row, packed as small as they'll go.
+    fn from_tiles(size: UVec2, tiles: impl IntoIterator<Item = TileId>) -> Chunk {
+        let tiles: Vec<TileId> = tiles.into_iter().collect();
+        let mut palette: Vec<TileId> = Vec::new();
+        for &tile in &tiles {
+            if !palette.contains(&tile) {
+                palette.push(tile);
+            }
+        }
+
+        let tiles = match palette[..] {
+            [] => Tiles::Single(TileId::default()),
+            [tile] => Tiles::Single(tile),
+            _ => {
+                let bits = bits_for(palette.len());
+                let mut words = vec![0; words_for(tiles.len(), bits)];
+                for (i, tile) in tiles.iter().enumerate() {
+                    let index = palette.iter().position(|t| t == tile).unwrap();
+                    write_bits(&mut words, bits, i, index as u64);
+                }
+                Tiles::Packed { palette, bits, words }
+            }
+        };
+
+        Chunk { width: size.x, height: size.y, tiles }
+    }
+
+    pub fn size(&self) -> UVec2 {
+        uvec2(self.width, self.height)
+    }
+
+    /// The tile at `pos` within the chunk, `None` if that's outside it.
+    pub fn get(&self, pos: UVec2) -> Option<TileId> {
+        let i = self.index(pos)?;
+        Some(match &self.tiles {
+            Tiles::Single(tile) => *tile,
+            Tiles::Packed { palette, bits, words } => palette[read_bits(words, *bits, i) as usize],
+        })
+    }
+
+    /// Puts `tile` at `pos` within the chunk, returning the tile that was there.
+    ///
+    /// # Panics
+    /// If `pos` is outside the chunk.
+    pub fn set(&mut self, pos: UVec2, tile: TileId) -> TileId {
+        let i = self.index(pos).expect("Tile outside chunk");
+        let len = (self.width * self.height) as usize;
+
+        if let Tiles::Single(old) = self.tiles {
+            if old == tile {
+                return old;
+            }
+            // Everything is still the old tile, which is palette entry 0
+            self.tiles = Tiles::Packed { palette: vec![old], bits: 1, words: vec![0; words_for(len, 1)] };
+        }
+        let Tiles::Packed { palette, bits, words } = &mut self.tiles else { unreachable!() };
+
+        let index = match palette.iter().position(|&t| t == tile) {
+            Some(index) => index,
+            None => {
+                palette.push(tile);
+                if palette.len() > 1 << *bits {
+                    // Out of room, so repack with twice the bits
+                    let wider = *bits * 2;
+                    let mut repacked = vec![0; words_for(len, wider)];
+                    for j in 0..len {
+                        write_bits(&mut repacked, wider, j, read_bits(words, *bits, j));
+                    }
+                    (*bits, *words) = (wider, repacked);
+                }
+                palette.len() - 1
+            }
+        };
+
+        let old = palette[read_bits(words, *bits, i) as usize];
+        write_bits(words, *bits, i, index as u64);
+        old
+    }
+
+    /// Every tile with its position within the chunk, row by row.
+    pub fn tiles(&self) -> impl Iterator<Item = (UVec2, TileId)> + '_ {
+        (0..self.height)
+            .flat_map(move |y| (0..self.width).map(move |x| uvec2(x, y)))
+            .map(|pos| (pos, self.get(pos).unwrap()))
+    }
+
+    fn index(&self, pos: UVec2) -> Option<usize> {
+        (pos.x < self.width && pos.y < self.height).then(|| (pos.y * self.width + pos.x) as usize)
+    }
+
+    pub fn draw(&self, offset: Vec2) {
+        for (point, tile) in self.tiles() {
+            draw_tile(tile, point.as_vec2() * 16. + offset);
+        }
+    }
+    pub fn dbg_draw(&self, offset: Vec2) {
+        for (point, tile) in self.tiles() {
+            let tile = tile.val().collision_result();
+
+            let color = if tile == CollisionResult::Solid { PURPLE } else { SKYBLUE } ;
+            draw_rectangle(
+                (point.x as f32 * 2.) + offset.x,
+                (point.y as f32 * 2.) + offset.y,
+                2.,
+                2.,
+                color
+            )
+        }
+    }
+}
+
+/// Chunks are the same if their tiles are, however they're stored.
+impl PartialEq for Chunk {
+    fn eq(&self, other: &Self) -> bool {
+        self.size() == other.size() && self.tiles().eq(other.tiles())
+    }
+}
+
+/// The fewest bits (that divide 64) it takes to count to `palette_len`.
+fn bits_for(palette_len: usize) -> u32 {
+    let needed = usize::BITS - (palette_len - 1).leading_zeros();
+    needed.max(1).next_power_of_two()
+}
+
+fn words_for(len: usize, bits: u32) -> usize {
+    (len * bits as usize).div_ceil(64)
+}
+
+fn read_bits(words: &[u64], bits: u32, i: usize) -> u64 {
+    let bit = i * bits as usize;
+    (words[bit / 64] >> (bit % 64)) & ((1 << bits) - 1)
+}
+
+fn write_bits(words: &mut [u64], bits: u32, i: usize, value: u64) {
+    let bit = i * bits as usize;
+    let mask = ((1 << bits) - 1) << (bit % 64);
+    words[bit / 64] = (words[bit / 64] & !mask) | (value << (bit % 64));
+}
+
+/// The most tiles a saved chunk can have, many times what chunks have ever had.
+const MAX_SAVED_TILES: u32 = 1 << 16;
+
+/// How a [`Chunk`] is saved: runs of the same tile, row by row, as indices into a palette.
+#[derive(Serialize, Deserialize)]
+#[serde(rename = "Chunk")]
+struct SavedChunk {
+    width: u32,
+    height: u32,
+    palette: Vec<TileId>,
+    /// How many tiles in a row are the same, and which palette entry they are.
+    runs: Vec<(u16, u8)>,
+}
+
+impl From<Chunk> for SavedChunk {
+    fn from(chunk: Chunk) -> Self {
+        let palette = match &chunk.tiles {
+            Tiles::Single(tile) => vec![*tile],
+            Tiles::Packed { palette, .. } => palette.clone(),
+        };
+
+        let mut runs: Vec<(u16, u8)> = Vec::new();
+        for (_, tile) in chunk.tiles() {
+            let index = palette.iter().position(|&t| t == tile).unwrap() as u8;
+            match runs.last_mut() {
+                Some((count, last)) if *last == index && *count < u16::MAX => *count += 1,
+                _ => runs.push((1, index)),
+            }
+        }
+
+        SavedChunk { width: chunk.width, height: chunk.height, palette, runs }
+    }
+}
+
+impl TryFrom<SavedChunk> for Chunk {
+    type Error = String;
+
+    fn try_from(saved: SavedChunk) -> Result<Self, Self::Error> {
+        // Straight off the disk, so nothing's taken on trust before it's allocated
+        let size = saved.width.checked_mul(saved.height)
+            .filter(|&size| size <= MAX_SAVED_TILES)
+            .ok_or_else(|| format!("Chunk is {}x{}, far bigger than any chunk", saved.width, saved.height))? as usize;
+        let mut tiles = Vec::new();
+        for (count, index) in saved.runs {
+            let tile = *saved.palette.get(index as usize).ok_or("Chunk palette index out of range")?;
+            if tiles.len() + count as usize > size {
+                return Err(format!("Chunk has more than the {size} tiles it should have"));
+            }
+            tiles.extend(std::iter::repeat(tile).take(count as usize));
+        }
+        if tiles.len() != size {
+            return Err(format!("Chunk has {} tiles, should have {size}", tiles.len()));
+        }
+
+        // Also drops anything in the palette that isn't used any more
+        Ok(Chunk::from_tiles(uvec2(saved.width, saved.height), tiles))
+    }
+}
+
+/// How a [`Chunk`] was saved before palettes, every tile in full.
+#[derive(Deserialize)]
+pub struct GridChunk(Grid<TileId>);
+
+impl From<GridChunk> for Chunk {
+    fn from(old: GridChunk) -> Self {
+        Chunk::from_tiles(uvec2(old.0.width as u32, old.0.height as u32), old.0.array)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chunks_pack_and_unpack() {
+        let size = uvec2(16, 14);
+        let mut chunk = Chunk::from_fn(size, |_| TileId::Air);
+        assert!(matches!(chunk.tiles, Tiles::Single(TileId::Air)));
+
+        // Every new kind of tile takes more bits, but the tiles stay where they were put
+        let placed = [TileId::Dirt, TileId::Stone, TileId::GoldOre, TileId::WoodLog, TileId::Grass];
+        for (i, &tile) in placed.iter().enumerate() {
+            assert_eq!(chunk.set(uvec2(i as u32, 3), tile), TileId::Air);
+        }
+        assert!(matches!(chunk.tiles, Tiles::Packed { bits: 4, .. }));
+        for (i, &tile) in placed.iter().enumerate() {
+            assert_eq!(chunk.get(uvec2(i as u32, 3)), Some(tile));
+        }
+        assert_eq!(chunk.get(uvec2(15, 13)), Some(TileId::Air));
+        assert_eq!(chunk.get(uvec2(16, 0)), None);
+
+        // Saved as a handful of runs, and back the same
+        let saved = SavedChunk::from(chunk.clone());
+        assert_eq!(saved.runs.len(), placed.len() + 2);
+        let data = bincode::serialize(&chunk).unwrap();
+        let grid = Grid::new_filled(16, 14, |pos| chunk.get(pos).unwrap(), TileId::Air);
+        assert!(data.len() < bincode::serialize(&grid).unwrap().len() / 10);
+        assert_eq!(bincode::deserialize::<Chunk>(&data).unwrap(), chunk);
+
+        // And so are chunks from before palettes
+        let old = bincode::deserialize::<GridChunk>(&bincode::serialize(&grid).unwrap()).unwrap();
+        assert_eq!(Chunk::from(old), chunk);
+    }
+
+    #[test]
+    fn damaged_chunks_dont_load() {
+        let saved = |width, height, runs| SavedChunk { width, height, palette: vec![TileId::Air], runs };
+        // Sizes that overflow, or are just too big to be real
+        assert!(Chunk::try_from(saved(1 << 16, 1 << 16, vec![(1, 0)])).is_err());
+        assert!(Chunk::try_from(saved(u32::MAX, 2, vec![(1, 0)])).is_err());
+        // More tiles than it says it has, or fewer
+        assert!(Chunk::try_from(saved(2, 2, vec![(u16::MAX, 0); 1000])).is_err());
+        assert!(Chunk::try_from(saved(2, 2, vec![(3, 0)])).is_err());
+        assert!(Chunk::try_from(saved(2, 2, vec![(4, 0)])).is_ok());
+    }
+}
@@ -2,7 +2,7 @@
 use bevy_ecs::prelude::*;
 use macroquad::prelude::*;
 
-use crate::{physics2::Collider, position::{RectExtend as _, ScreenPos}, tile::TileId, TILE_SET, TILE_SIZE};
+use crate::{input::{Action, InputActions}, physics2::Collider, position::{RectExtend as _, ScreenPos}, tile::TileId, TILE_SET, TILE_SIZE};
 
 use super::{camera::GameCamera, player::{Player, PlayerTag}, tile_map::ChunkMap, ui::{COLOR_BORDER, COLOR_HIGHLIGHT}};
 
@@ -18,7 +18,9 @@ use super::{camera::GameCamera, player::{Player, PlayerTag}, tile_map::ChunkMap,
 
 #[derive(Component)]
 pub struct Cursor {
-    position: Option<ScreenPos>
+    position: Option<ScreenPos>,
+    /// The last placement was refused because the tile overlaps the player.
+    blocked: bool,
 }
 impl Cursor {
     pub fn on_screen(&self) -> bool {
@@ -27,43 +29,39 @@ impl Cursor {
 }
 
 pub(super) fn init_cursor(mut commands: Commands) {
-    let pos = ScreenPos::mouse();
-    
     commands.spawn(Cursor {
-        position: pos
+        position: None,
+        blocked: false,
     });
 }
 
-pub(super) fn update_cursor(mut cursor: Query<&mut Cursor>, camera: Query<&GameCamera>, player: Query<(&Player, &Collider), With<PlayerTag>>, mut map: Query<&mut ChunkMap>) {
+pub(super) fn update_cursor(mut cursor: Query<&mut Cursor>, input: Res<InputActions>) {
+    let mut cursor = cursor.single_mut();
+    cursor.position = input.cursor;
+}
+
+pub(super) fn edit_tiles(mut cursor: Query<&mut Cursor>, player: Query<(&Player, &Collider), With<PlayerTag>>, mut map: Query<&mut ChunkMap>, input: Res<InputActions>) {
     let mut cursor = cursor.single_mut();
-    let cursor = cursor.as_mut();
     let (player, collider) = player.single();
-    let camera = camera.single();
     let mut map = map.single_mut();
     
-    let pos = ScreenPos::mouse();
-    
-    *cursor = Cursor {
-        position: pos
-    };
+    cursor.blocked = false;
     
-    if let Some(pos) = cursor.position {
-        use MouseButton::*;
+    if let Some(pos) = input.aim {
         use TileId::*;
         
-        let pos = pos.to_world(&camera.0);
-        if is_mouse_button_down(Left) ^ is_mouse_button_down(Right) {
-            let tile = if is_mouse_button_down(Left) {
+        let mine = input.pressed(Action::Mine);
+        let place = input.pressed(Action::Place);
+        if mine ^ place {
+            let tile = if mine {
                 Air
             } else {
                 player.get_inventory_item()
             };
             let player_collider = Rect::from_vecs(collider.pos, ivec2(collider.width, collider.height).as_vec2());
-            map.place_tile(player_collider, pos, tile);
+            cursor.blocked = !map.place_tile(player_collider, pos, tile);
         }
     }
-    
-    
 }
 
 pub(super) fn draw_cursor(cursor: Query<&Cursor>, camera: Query<&GameCamera>) {
@@ -76,6 +74,10 @@ pub(super) fn draw_cursor(cursor: Query<&Cursor>, camera: Query<&GameCamera>) {
         
         let tile_pos = pos.snap().0;
         
+        if cursor.blocked {
+            draw_rectangle(tile_pos.x, tile_pos.y, TILE_SIZE, TILE_SIZE, RED);
+        }
+        
         draw_line(
             tile_pos.x  + 1.,
             tile_pos.y + TILE_SIZE,
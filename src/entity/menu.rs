@@ -0,0 +1,324 @@
+use bevy_ecs::prelude::*;
+use macroquad::prelude::*;
+
+use crate::app::AppExit;
+use crate::input::{Action, InputActions};
+use crate::physics2::Collider;
+use crate::position::ScreenPos;
+use crate::save::slots::{self, CurrentSlot, WorldSlot};
+use crate::save::{LoadFailed, SaveError, Storage};
+use crate::worldgen;
+use crate::state::{GameState, State};
+use crate::{DEFAULT_FONT, IS_WASM, VIRTUAL_HEIGHT, VIRTUAL_WIDTH};
+
+use super::camera::GameCamera;
+use super::player::PlayerTag;
+use super::tile_map::ChunkMap;
+use super::ui::{COLOR_BASE, COLOR_BORDER, COLOR_HIGHLIGHT, COLOR_SURFACE};
+
+/// How long a world's name can be, so it fits on the screen.
+const MAX_NAME: usize = 20;
+
+/// The main menu's list of worlds, and whatever's being done to one of them.
+#[derive(Resource, Default)]
+pub(super) struct WorldMenu {
+    /// Every world with how much storage it takes up.
+    worlds: Vec<(WorldSlot, usize)>,
+    /// The world that's picked. One past the last one is a new world.
+    selected: usize,
+    editing: Option<Editing>,
+    /// What went wrong with the last thing that was tried.
+    error: Option<String>,
+}
+
+/// Something being typed in the world menu.
+enum Editing {
+    NewName(String),
+    NewSeed { name: String, seed: String },
+    Rename(String),
+    Duplicate(String),
+    /// Not typing, just making sure.
+    Delete,
+}
+
+impl WorldMenu {
+    fn refresh(&mut self, storage: &Storage) {
+        match slots::list(storage) {
+            Ok(worlds) => {
+                self.worlds = worlds.into_iter()
+                    .map(|slot| {
+                        let size = ChunkMap::saved_size(storage, &slot.key);
+                        (slot, size)
+                    })
+                    .collect();
+            }
+            Err(error) => self.error = Some(error.to_string()),
+        }
+        self.selected = self.selected.min(self.worlds.len());
+    }
+
+    fn picked(&self) -> Option<&WorldSlot> {
+        self.worlds.get(self.selected).map(|(slot, _)| slot)
+    }
+
+    /// Does whatever was being typed for.
+    fn finish(&mut self, editing: Editing, storage: &Storage) {
+        let default_name = format!("World {}", self.worlds.len() + 1);
+        let named = |name: String| if name.trim().is_empty() { default_name.clone() } else { name };
+        let key = self.picked().map(|slot| slot.key.clone());
+
+        let done = match (editing, key) {
+            (Editing::NewName(name), _) => {
+                self.editing = Some(Editing::NewSeed { name, seed: String::new() });
+                return;
+            }
+            (Editing::NewSeed { name, seed }, _) => {
+                let seed = seed.parse().unwrap_or_else(|_| worldgen::random_seed());
+                slots::create(storage, &named(name), seed).map(|_| self.selected = 0)
+            }
+            (Editing::Rename(name), Some(key)) => slots::rename(storage, &key, &named(name)),
+            (Editing::Duplicate(name), Some(key)) => slots::duplicate(storage, &key, &named(name)).map(|_| self.selected = 0),
+            (Editing::Delete, Some(key)) => slots::delete(storage, &key),
+            _ => Ok(()),
+        };
+        self.error = done.err().map(|error| error.to_string());
+        self.refresh(storage);
+    }
+}
+
+impl Editing {
+    fn type_in(&mut self, typed: &str) {
+        let (text, max, allowed): (_, _, fn(char) -> bool) = match self {
+            Editing::NewName(name) | Editing::Rename(name) | Editing::Duplicate(name) => (name, MAX_NAME, |_| true),
+            // Any more digits could be too big for a seed
+            Editing::NewSeed { seed, .. } => (seed, 9, |c| c.is_ascii_digit()),
+            Editing::Delete => return,
+        };
+        for c in typed.chars() {
+            if c == '\u{8}' {
+                text.pop();
+            } else if allowed(c) && text.chars().count() < max {
+                text.push(c);
+            }
+        }
+    }
+}
+
+pub(super) fn open_world_menu(mut menu: ResMut<WorldMenu>, storage: Res<Storage>) {
+    menu.editing = None;
+    menu.refresh(&storage);
+}
+
+pub(super) fn main_menu_input(
+    input: Res<InputActions>,
+    mut state: ResMut<State>,
+    mut menu: ResMut<WorldMenu>,
+    storage: Res<Storage>,
+    mut commands: Commands,
+) {
+    // Mine too, so a click or a tap anywhere picks
+    let confirm = input.just_pressed(Action::Confirm) || input.just_pressed(Action::Mine);
+
+    if let Some(editing) = &mut menu.editing {
+        // Letters are for typing now, not for their actions. Deleting can't be undone, so only Enter does it,
+        // not a stray click or tap.
+        if input.just_pressed(Action::Pause) {
+            menu.editing = None;
+        } else if input.just_pressed(Action::Confirm) || (confirm && !matches!(editing, Editing::Delete)) {
+            let editing = menu.editing.take().unwrap();
+            menu.finish(editing, &storage);
+        } else {
+            editing.type_in(&input.typed);
+        }
+        return;
+    }
+
+    let count = menu.worlds.len() + 1;
+    if input.just_pressed(Action::MoveLeft) {
+        menu.selected = (menu.selected + count - 1) % count;
+    } else if input.just_pressed(Action::MoveRight) {
+        menu.selected = (menu.selected + 1) % count;
+    }
+
+    let picked = menu.picked().cloned();
+    if confirm {
+        match picked {
+            Some(slot) => {
+                commands.insert_resource(CurrentSlot(slot.key));
+                state.set(GameState::Loading);
+            }
+            None => menu.editing = Some(Editing::NewName(String::new())),
+        }
+        return;
+    }
+
+    // Managing worlds is keyboard only, the same as naming them. Touch screens can play and make worlds,
+    // but not rename, copy or delete them.
+    for c in input.typed.chars() {
+        menu.editing = match (c.to_ascii_lowercase(), &picked) {
+            ('n', _) => Some(Editing::NewName(String::new())),
+            ('r', Some(slot)) => Some(Editing::Rename(slot.name.clone())),
+            ('c', Some(slot)) => Some(Editing::Duplicate(format!("{} copy", slot.name).chars().take(MAX_NAME).collect())),
+            ('x', Some(_)) => Some(Editing::Delete),
+            _ => continue,
+        };
+        menu.error = None;
+        return;
+    }
+
+    if input.just_pressed(Action::Quit) && !IS_WASM {
+        commands.insert_resource(AppExit);
+    }
+}
+
+pub(super) fn pause_input(input: Res<InputActions>, mut state: ResMut<State>) {
+    let paused = state.get() == GameState::Paused;
+
+    if input.just_pressed(Action::Pause) {
+        state.set(if paused { GameState::Playing } else { GameState::Paused });
+    } else if paused && input.just_pressed(Action::Quit) {
+        state.set(GameState::MainMenu);
+    }
+}
+
+pub(super) fn load_failed_input(
+    input: Res<InputActions>,
+    mut state: ResMut<State>,
+    mut failed: ResMut<LoadFailed>,
+    slot: Res<CurrentSlot>,
+    storage: Res<Storage>,
+    mut commands: Commands,
+) {
+    if input.just_pressed(Action::Confirm) || input.just_pressed(Action::Mine) {
+        // Without a backup, the save is kept out of the way of the new world instead
+        let fixed = if failed.has_backup { ChunkMap::restore_backup(&storage, &slot.0) } else { ChunkMap::set_aside_save(&storage, &slot.0) };
+        match fixed {
+            Ok(()) => {
+                commands.remove_resource::<LoadFailed>();
+                state.set(GameState::Loading);
+            }
+            Err(error) => {
+                println!("Couldn't restore the backup: {error}");
+                *failed = LoadFailed { error, has_backup: false };
+            }
+        }
+    } else if input.just_pressed(Action::Quit) || input.just_pressed(Action::Pause) {
+        commands.remove_resource::<LoadFailed>();
+        state.set(GameState::MainMenu);
+    }
+}
+
+/// The click or tap that picked the world can still be held when it starts, and shouldn't mine as well.
+pub(super) fn ignore_held_click(mut input: ResMut<InputActions>) {
+    input.ignore_until_released(Action::Mine);
+}
+
+/// Nothing moves while paused, so stop drawing the player partway between two ticks.
+pub(super) fn settle_player(mut player: Query<&mut Collider, With<PlayerTag>>) {
+    for mut collider in &mut player {
+        collider.begin_tick();
+    }
+}
+
+pub(super) fn draw_main_menu(camera: Query<&GameCamera>, menu: Res<WorldMenu>) {
+    let origin = ScreenPos(Vec2::ZERO).to_world(&camera.single().0).0;
+    let picked = menu.worlds.get(menu.selected);
+
+    draw_rectangle(origin.x, origin.y, VIRTUAL_WIDTH, VIRTUAL_HEIGHT, Color::from_hex(COLOR_BASE));
+    draw_centred_text("dig", origin + vec2(0.0, 56.0), 48, COLOR_HIGHLIGHT);
+
+    let (title, detail, help) = match (&menu.editing, picked) {
+        (Some(Editing::NewName(name)), _) => ("Name the new world".to_owned(), format!("{name}_"), "Enter for the seed"),
+        (Some(Editing::NewSeed { seed, .. }), _) => ("Pick a seed, or leave it blank".to_owned(), format!("{seed}_"), "Enter to make it"),
+        (Some(Editing::Rename(name)), _) => ("Rename to".to_owned(), format!("{name}_"), "Enter to rename it"),
+        (Some(Editing::Duplicate(name)), _) => ("Copy it as".to_owned(), format!("{name}_"), "Enter to copy it"),
+        (Some(Editing::Delete), Some((slot, _))) => (format!("Delete {}?", slot.name), "It's gone for good".to_owned(), "Enter to delete it"),
+        (_, Some((slot, size))) => (format!("< {} >", slot.name), describe_world(slot, *size), "Enter or tap to play"),
+        (_, None) => ("< New world >".to_owned(), String::new(), "Enter or tap to make one"),
+    };
+    draw_centred_text(&title, origin + vec2(0.0, 100.0), 16, COLOR_HIGHLIGHT);
+    draw_centred_text(&detail, origin + vec2(0.0, 116.0), 16, COLOR_SURFACE);
+    draw_centred_text(help, origin + vec2(0.0, 148.0), 16, COLOR_SURFACE);
+
+    if menu.editing.is_some() {
+        draw_centred_text("Esc to cancel", origin + vec2(0.0, 164.0), 16, COLOR_SURFACE);
+    } else {
+        draw_centred_text("A and D to pick, N for a new world", origin + vec2(0.0, 164.0), 16, COLOR_SURFACE);
+        if picked.is_some() {
+            draw_centred_text("R rename, C copy, X delete", origin + vec2(0.0, 180.0), 16, COLOR_SURFACE);
+        }
+        if !IS_WASM {
+            draw_centred_text("F10 to quit", origin + vec2(0.0, 196.0), 16, COLOR_SURFACE);
+        }
+    }
+    if let Some(error) = &menu.error {
+        draw_centred_text(error, origin + vec2(0.0, 216.0), 16, COLOR_HIGHLIGHT);
+    }
+}
+
+/// Its seed, size and when it was last played, as in "seed 42, 12 KB, 3 days ago".
+fn describe_world(slot: &WorldSlot, size: usize) -> String {
+    let ago = (macroquad::miniquad::date::now() - slot.last_played).max(0.0) as u64;
+    let ago = match ago {
+        0..60 => "just now".to_owned(),
+        60..3600 => format!("{} min ago", ago / 60),
+        3600..86400 => format!("{} h ago", ago / 3600),
+        _ => format!("{} days ago", ago / 86400),
+    };
+    let size = format!("{} KB", size.div_ceil(1024));
+
+    match slot.seed {
+        Some(seed) => format!("seed {seed}, {size}, {ago}"),
+        None => format!("{size}, {ago}"),
+    }
+}
+
+pub(super) fn draw_pause_menu(camera: Query<&GameCamera>) {
+    let origin = ScreenPos(Vec2::ZERO).to_world(&camera.single().0).0;
+
+    let mut shade = Color::from_hex(COLOR_BORDER);
+    shade.a = 0.6;
+    draw_rectangle(origin.x, origin.y, VIRTUAL_WIDTH, VIRTUAL_HEIGHT, shade);
+    draw_centred_text("Paused", origin + vec2(0.0, 96.0), 32, COLOR_HIGHLIGHT);
+    draw_centred_text("Esc to resume", origin + vec2(0.0, 128.0), 16, COLOR_SURFACE);
+    draw_centred_text("F10 to save and quit", origin + vec2(0.0, 144.0), 16, COLOR_SURFACE);
+}
+
+pub(super) fn draw_load_failed(camera: Query<&GameCamera>, failed: Res<LoadFailed>) {
+    let origin = ScreenPos(Vec2::ZERO).to_world(&camera.single().0).0;
+
+    let why = match failed.error {
+        SaveError::Missing => "It's gone missing.",
+        SaveError::Corrupt(_) => "It's been damaged.",
+        SaveError::Incompatible { .. } => "It's from a newer version of the game.",
+        SaveError::Write(_) => "It couldn't be read.",
+    };
+    let fix = if failed.has_backup { "Enter or tap to restore the backup" } else { "Enter or tap to start a new world" };
+
+    draw_rectangle(origin.x, origin.y, VIRTUAL_WIDTH, VIRTUAL_HEIGHT, Color::from_hex(COLOR_BASE));
+    draw_centred_text("Couldn't load your world", origin + vec2(0.0, 80.0), 16, COLOR_HIGHLIGHT);
+    draw_centred_text(why, origin + vec2(0.0, 96.0), 16, COLOR_SURFACE);
+    draw_centred_text(fix, origin + vec2(0.0, 140.0), 16, COLOR_SURFACE);
+    if !failed.has_backup {
+        draw_centred_text("(the old one is kept)", origin + vec2(0.0, 156.0), 16, COLOR_SURFACE);
+    }
+    draw_centred_text("Esc to go back", origin + vec2(0.0, 180.0), 16, COLOR_SURFACE);
+}
+
+/// Draws `text` centred horizontally on the screen starting at `origin`, with its baseline at `origin.y`.
+fn draw_centred_text(text: &str, origin: Vec2, font_size: u16, color: u32) {
+    let font = &*DEFAULT_FONT;
+    let width = measure_text(text, Some(font), font_size, 1.0).width;
+
+    draw_text_ex(
+        text,
+        (origin.x + (VIRTUAL_WIDTH - width) / 2.0).floor(),
+        origin.y,
+        TextParams {
+            font: Some(font),
+            font_size,
+            color: Color::from_hex(color),
+            ..Default::default()
+        }
+    );
+}
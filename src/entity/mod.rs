@@ -1,43 +1,408 @@
+use bevy_ecs::schedule::common_conditions::{not, resource_exists};
+use bevy_ecs::entity::Entity;
+use bevy_ecs::query::{Or, With};
 use bevy_ecs::schedule::IntoSystemConfigs;
+use bevy_ecs::world::World;
 use camera::init_camera;
+use camera::GameCamera;
 use camera::letterbox_camera;
 use camera::refocus_camera;
 use camera::setup_camera;
 use cursor::draw_cursor;
+use cursor::edit_tiles;
 use cursor::init_cursor;
 use cursor::update_cursor;
+use menu::{draw_load_failed, draw_main_menu, draw_pause_menu, ignore_held_click, load_failed_input, main_menu_input, open_world_menu, pause_input, settle_player, WorldMenu};
 use player::draw_player;
 use player::move_player;
+use player::new_player;
+use player::restore_player;
+use player::Player;
+use player::SavedPlayer;
+use player::PlayerTag;
+use player::select_item;
 use tile_map::draw_map;
 use tile_map::init_map;
+use tile_map::send_map_events;
+use tile_map::save_after_edits;
 use tile_map::timed_save;
+use tile_map::unload_chunks;
+use tile_map::ChunkMap;
+use tile_map::SaveTimer;
+use ui::draw_touch_controls;
 use ui::draw_ui;
 use ui::init_ui;
 
 use crate::app::ScheduleLabel_::*;
-use crate::app::Plugin;
+use crate::app::{windowed, GameSet, Plugin};
+use crate::events::WorldSaved;
+use crate::physics2::Collider;
+use crate::save::background::{SaveInProgress, SaveStep};
+use crate::save::scene::{Saveable, SavedScene};
+use crate::save::SaveError;
+use macroquad::math::Vec2;
+use crate::input::InputTick;
+use crate::replay::{in_replay, Playback};
+use crate::state::{in_game, in_state, GameState};
+use crate::SAVE_TIMER;
 
+pub mod chunk;
 pub mod player;
 pub mod tile_map;
 pub mod camera;
 pub mod ui;
 pub mod cursor;
+mod menu;
 
 pub struct EntityPlugin;
 
+/// Spawns `chunk_map` and the player in it, as they were saved or fresh at the spawn point.
+pub fn spawn_world(world: &mut World, mut chunk_map: ChunkMap, player: Option<SavedPlayer>) {
+    let (new, collider, actor) = match &player {
+        Some(saved) => restore_player(&mut chunk_map, saved),
+        None => new_player(&mut chunk_map),
+    };
+    if let (Some(saved), Ok(mut camera)) = (&player, world.query::<&mut GameCamera>().get_single_mut(world)) {
+        camera.0.target = saved.camera_target;
+    }
+    world.spawn(chunk_map);
+    world.spawn((PlayerTag, new, collider, actor));
+}
+
+/// Saves the world right away, once any save that's already being written is done.
+pub fn save_world(world: &mut World) {
+    finish_saving(world);
+    if let Some(steps) = save_steps(world) {
+        let result = steps.into_iter().try_for_each(|step| step());
+        saved(world, result);
+    }
+}
+
+/// Starts saving the world in the background. [`poll_save`] says when it's done.
+pub fn start_saving(world: &mut World) {
+    if let Some(steps) = save_steps(world) {
+        world.insert_resource(SaveInProgress::start(steps));
+    }
+}
+
+/// Checks on the save being written in the background, and writes the next bit of it if that's
+/// how it's being written.
+fn poll_save(world: &mut World) {
+    let Some(mut save) = world.get_resource_mut::<SaveInProgress>() else { return };
+    if let Some(result) = save.poll() {
+        world.remove_resource::<SaveInProgress>();
+        saved(world, result);
+    }
+}
+
+/// Waits for the save being written in the background, if there is one.
+fn finish_saving(world: &mut World) {
+    if let Some(save) = world.remove_resource::<SaveInProgress>() {
+        let result = save.finish();
+        saved(world, result);
+    }
+}
+
+/// Sends [`WorldSaved`] if it worked.
+fn saved(world: &mut World, result: Result<(), SaveError>) {
+    match result {
+        Ok(()) => { world.send_event(WorldSaved); }
+        Err(e) => {
+            println!("Couldn't save: {e}");
+            // Some of the chunks might not have been written, so write them all next time
+            if let Ok(mut map) = world.query::<&mut ChunkMap>().get_single_mut(world) {
+                map.save_failed();
+            }
+        }
+    }
+}
+
+/// A snapshot of everything there is to save, as steps that write it: the map, the player in it along
+/// with where the camera's looking, and every [`Saveable`] entity. `None` if there's no world.
+fn save_steps(world: &mut World) -> Option<Vec<SaveStep>> {
+    let player = saved_player(world);
+    let scene = SavedScene::from_world(world);
+
+    let mut map = world.query::<&mut ChunkMap>().get_single_mut(world).ok()?;
+    let (storage, slot) = (map.storage().clone(), map.slot().to_owned());
+    let mut steps = map.save_steps();
+    if let Some(player) = player {
+        let (storage, slot) = (storage.clone(), slot.clone());
+        steps.push(Box::new(move || player.save(&storage, &slot)));
+    }
+    steps.push(Box::new(move || scene?.save(&storage, &slot)));
+    Some(steps)
+}
+
+/// The player as they'd be saved right now, along with where the camera's looking. `None` if there's no player.
+pub fn saved_player(world: &mut World) -> Option<SavedPlayer> {
+    let camera_target = world.query::<&GameCamera>().get_single(world).map_or(Vec2::ZERO, |camera| camera.0.target);
+    world
+        .query_filtered::<(&Player, &Collider), With<PlayerTag>>()
+        .get_single(world)
+        .ok()
+        .map(|(player, collider)| SavedPlayer::new(player, collider, camera_target))
+}
+
+/// Saves the world (unless it's a replay being played back) and removes the map, the player and everything else saved with it.
+pub fn despawn_world(world: &mut World) {
+    finish_saving(world);
+    if !world.contains_resource::<Playback>() {
+        save_world(world);
+    }
+
+    let entities: Vec<Entity> = world
+        .query_filtered::<Entity, Or<(With<ChunkMap>, With<PlayerTag>, With<Saveable>)>>()
+        .iter(world)
+        .collect();
+    for entity in entities {
+        world.despawn(entity);
+    }
+    world.insert_resource(SaveTimer(SAVE_TIMER));
+}
+
 impl Plugin for EntityPlugin {
     fn build(&self, app: &mut crate::app::App) {
         app
             .add_systems(Startup, (
                 init_camera, init_map, init_cursor, init_ui)
             )
+            .init_resource::<WorldMenu>()
+            .add_systems(OnEnter(GameState::MainMenu), (despawn_world, open_world_menu).chain())
+            .add_systems(OnEnter(GameState::Paused), settle_player)
+            .add_systems(OnEnter(GameState::Playing), ignore_held_click)
+            .add_systems(PreUpdate, poll_save)
+            // Closing the game or the tab would lose everything since the last save
+            .add_systems(Suspend, save_world.run_if(in_game).run_if(not(resource_exists::<Playback>)))
+            .add_systems(FixedUpdate, (
+                main_menu_input.run_if(in_state(GameState::MainMenu)),
+                load_failed_input.run_if(in_state(GameState::LoadFailed)),
+                pause_input.run_if(in_game),
+                select_item.run_if(in_state(GameState::Playing)),
+                ).in_set(GameSet::Input)
+            )
+            .add_systems(FixedUpdate, (
+                edit_tiles,
+                // Replays are played on a copy of the world, keep them out of the save
+                (save_after_edits, timed_save).chain().run_if(not(resource_exists::<Playback>)),
+                // Playback only has what's in memory, so recording has to keep every chunk it's used there too.
+                // A save being written might have copies of them older than what would be unloaded
+                unload_chunks.run_if(not(in_replay)).run_if(not(resource_exists::<SaveInProgress>)),
+                ).chain().run_if(in_state(GameState::Playing)).in_set(GameSet::Simulation)
+            )
+            .add_systems(FixedUpdate, move_player.run_if(in_state(GameState::Playing)).in_set(GameSet::Physics))
+            // Physics generates chunks too, so wait for it before sending what happened to the map
+            .add_systems(FixedUpdate, send_map_events.after(GameSet::Physics).before(InputTick::End))
+            .add_systems(Update, (
+                (refocus_camera.run_if(in_game), setup_camera.run_if(windowed)).chain(),
+                update_cursor,
+                ).in_set(GameSet::Camera)
+            )
             .add_systems(Update, (
-                (timed_save, update_cursor),
-                (draw_map, draw_cursor, draw_ui).chain(),
-                (draw_player, move_player, refocus_camera).chain(),
-                ).chain()
+                draw_map,
+                draw_cursor,
+                draw_player,
+                ).chain().run_if(in_game).in_set(GameSet::RenderWorld)
             )
-            .add_systems(PreUpdate, setup_camera)
-            .add_systems(PostUpdate, letterbox_camera);
+            .add_systems(Update, (
+                (draw_ui, draw_touch_controls).chain().run_if(in_game),
+                draw_pause_menu.run_if(in_state(GameState::Paused)),
+                draw_main_menu.run_if(in_state(GameState::MainMenu)),
+                draw_load_failed.run_if(in_state(GameState::LoadFailed)),
+                ).chain().in_set(GameSet::RenderUi)
+            )
+            .add_systems(PostUpdate, letterbox_camera.run_if(windowed));
+    }
+}
+#[cfg(test)]
+mod tests {
+    use bevy_ecs::event::Events;
+    use bevy_ecs::query::With;
+    use macroquad::math::{ivec2, uvec2, vec2, Rect};
+    use crate::app::App;
+    use crate::events::{TileChanged, WorldSaved};
+    use crate::input::{Action, InputActions, InputPlugin};
+    use crate::physics2::Collider;
+    use crate::position::{ChunkPos, TilePos, WorldPos};
+    use crate::tile::TileId;
+    use crate::state::{GameState, State};
+    use crate::time::{Time, FIXED_TIMESTEP};
+    use crate::worldgen::FlatGenerator;
+    use super::player::{Player, PlayerTag, SavedPlayer};
+    use super::tile_map::ChunkMap;
+    use super::{despawn_world, spawn_world, EntityPlugin};
+
+    /// An app with the player standing at the spawn point, on flat dirt starting at row 8.
+    fn headless_app() -> App {
+        let mut app = App::headless();
+        app
+            .add_plugin(InputPlugin)
+            .add_plugin(EntityPlugin)
+            .insert_resource(State::new(GameState::Playing));
+
+        spawn_world(&mut app.world, ChunkMap::with_generator(FlatGenerator::default()), None);
+
+        app.startup();
+        app
+    }
+
+    fn player_pos(app: &mut App) -> macroquad::math::Vec2 {
+        app.world.query::<&Collider>().single(&app.world).pos
+    }
+
+    #[test]
+    fn player_walks_right_and_lands_on_dirt() {
+        let mut app = headless_app();
+        app.world.query::<&mut Collider>().single_mut(&mut app.world).teleport(vec2(128., 40.));
+
+        app.world.resource_mut::<InputActions>().set(Action::MoveRight, true);
+        app.step(120, FIXED_TIMESTEP);
+
+        // Dirt starts at row 8, so standing on it puts the player at y = 112
+        assert_eq!(player_pos(&mut app), vec2(128. + 240., 112.));
+    }
+
+    #[test]
+    fn movement_is_frame_rate_independent() {
+        let mut slow = headless_app();
+        let mut fast = headless_app();
+
+        slow.world.resource_mut::<InputActions>().set(Action::MoveLeft, true);
+        fast.world.resource_mut::<InputActions>().set(Action::MoveLeft, true);
+        slow.step(30, 1.0 / 30.0);
+        fast.step(144, 1.0 / 144.0);
+
+        // The clocks can disagree by a tick through float error, so compare each
+        // against a run of exactly as many ticks
+        for app in [&mut slow, &mut fast] {
+            let ticks = app.world.resource::<Time>().ticks();
+            assert!(ticks.abs_diff(60) <= 1);
+
+            let mut reference = headless_app();
+            reference.world.resource_mut::<InputActions>().set(Action::MoveLeft, true);
+            reference.step(ticks as u32, FIXED_TIMESTEP);
+            assert_eq!(player_pos(app), player_pos(&mut reference));
+        }
+    }
+
+    #[test]
+    fn pausing_stops_the_world() {
+        let mut app = headless_app();
+
+        app.world.resource_mut::<InputActions>().set(Action::Pause, true);
+        app.step(2, FIXED_TIMESTEP);
+        assert_eq!(app.world.resource::<State>().get(), GameState::Paused);
+
+        let paused_at = player_pos(&mut app);
+        {
+            let mut input = app.world.resource_mut::<InputActions>();
+            input.set(Action::Pause, false);
+            input.set(Action::MoveRight, true);
+        }
+        app.step(30, FIXED_TIMESTEP);
+        assert_eq!(player_pos(&mut app), paused_at);
+
+        app.world.resource_mut::<InputActions>().set(Action::Pause, true);
+        app.step(30, FIXED_TIMESTEP);
+        assert_eq!(app.world.resource::<State>().get(), GameState::Playing);
+        assert!(player_pos(&mut app).x > paused_at.x);
+    }
+
+    #[test]
+    fn mining_sends_tile_changed() {
+        let mut app = headless_app();
+        let surface = app.world.query::<&ChunkMap>().single(&app.world).surface_height(2);
+
+        {
+            let mut input = app.world.resource_mut::<InputActions>();
+            input.aim = Some(WorldPos(vec2(40., surface as f32 * 16. + 8.)));
+            input.set(Action::Mine, true);
+        }
+        // Mining the same tile again on the second tick isn't a change
+        app.step(2, FIXED_TIMESTEP);
+
+        let events = app.world.resource::<Events<TileChanged>>();
+        let changed: Vec<TileChanged> = events.get_reader().read(events).copied().collect();
+        assert_eq!(changed, vec![TileChanged { pos: TilePos(vec2(2., surface as f32)), from: TileId::Dirt, to: TileId::Air }]);
+    }
+
+    #[test]
+    fn a_click_held_into_the_game_doesnt_mine() {
+        let mut app = headless_app();
+        let surface = app.world.query::<&ChunkMap>().single(&app.world).surface_height(2);
+        let tile = |app: &mut App| {
+            let mut map = app.world.query::<&mut ChunkMap>().single_mut(&mut app.world);
+            map.get(ChunkPos(ivec2(0, surface.div_euclid(14)))).get(uvec2(2, surface.rem_euclid(14) as u32))
+        };
+
+        // Picked from a menu with a click that's still held
+        app.world.resource_mut::<State>().set(GameState::Paused);
+        app.step(1, FIXED_TIMESTEP);
+        {
+            let mut input = app.world.resource_mut::<InputActions>();
+            input.aim = Some(WorldPos(vec2(40., surface as f32 * 16. + 8.)));
+            input.set(Action::Mine, true);
+        }
+        app.world.resource_mut::<State>().set(GameState::Playing);
+        for _ in 0..5 {
+            app.world.resource_mut::<InputActions>().set(Action::Mine, true);
+            app.step(1, FIXED_TIMESTEP);
+        }
+        assert_eq!(tile(&mut app), Some(TileId::Dirt));
+
+        app.world.resource_mut::<InputActions>().set(Action::Mine, false);
+        app.step(1, FIXED_TIMESTEP);
+        app.world.resource_mut::<InputActions>().set(Action::Mine, true);
+        app.step(1, FIXED_TIMESTEP);
+        assert_eq!(tile(&mut app), Some(TileId::Air));
+    }
+
+    #[test]
+    fn suspending_saves_the_world() {
+        let mut app = headless_app();
+        let surface = app.world.query::<&ChunkMap>().single(&app.world).surface_height(2);
+        {
+            let mut input = app.world.resource_mut::<InputActions>();
+            input.aim = Some(WorldPos(vec2(40., surface as f32 * 16. + 8.)));
+            input.set(Action::Mine, true);
+        }
+        app.step(1, FIXED_TIMESTEP);
+
+        // Long before the timer's up
+        app.suspend();
+        let events = app.world.resource::<Events<WorldSaved>>();
+        assert_eq!(events.get_reader().read(events).count(), 1);
+        let (storage, slot) = {
+            let map = app.world.query::<&ChunkMap>().single(&app.world);
+            (map.storage().clone(), map.slot().to_owned())
+        };
+        let mut saved = ChunkMap::load(&storage, &slot).unwrap();
+        let chunk = saved.get(ChunkPos(ivec2(0, surface.div_euclid(14))));
+        assert_eq!(chunk.get(uvec2(2, surface.rem_euclid(14) as u32)), Some(TileId::Air));
+    }
+
+    #[test]
+    fn player_comes_back_where_they_were_saved() {
+        let mut app = headless_app();
+        app.world.query::<&mut Collider>().single_mut(&mut app.world).teleport(vec2(200., 112.));
+        app.world.query::<&mut Player>().single_mut(&mut app.world).selected_item = 2;
+        let (storage, slot) = {
+            let map = app.world.query::<&ChunkMap>().single(&app.world);
+            (map.storage().clone(), map.slot().to_owned())
+        };
+        despawn_world(&mut app.world);
+
+        let saved = SavedPlayer::load(&storage, &slot).unwrap();
+        assert_eq!((saved.pos, saved.selected_item), (vec2(200., 112.), 2));
+
+        // Filled in since, so they're put on top of what's there now
+        let mut map = ChunkMap::load(&storage, &slot).unwrap();
+        for y in 0..2 {
+            assert!(map.place_tile(Rect::default(), WorldPos(vec2(200., 112. - y as f32 * 16.)), TileId::Stone));
+        }
+        spawn_world(&mut app.world, map, Some(saved));
+        let (player, collider) = app.world.query_filtered::<(&Player, &Collider), With<PlayerTag>>().single(&app.world);
+        assert_eq!(collider.pos, vec2(200., 80.));
+        assert_eq!(player.selected_item, 2);
     }
-}
\ No newline at end of file
+}
@@ -1,13 +1,19 @@
 use bevy_ecs::component::Component;
 use bevy_ecs::query::With;
-use bevy_ecs::system::Query;
+use bevy_ecs::event::EventWriter;
+use bevy_ecs::system::{Query, Res};
+use crate::events::ChunkFocused;
+use crate::input::{Action, InputActions};
 use macroquad::prelude::*;
 use crate::physics2::{move_h, move_v, Collider, CollisionResult};
 use crate::position::{RectExtend, WorldPos};
 use crate::tile::TileId;
-use crate::entity::tile_map::ChunkMap;
+use crate::entity::tile_map::{player_key, ChunkMap};
 use crate::entity::ui::draw_from_tile_set;
-use crate::{IS_WASM, TILE_SIZE, VIRTUAL_HEIGHT, VIRTUAL_WIDTH};
+use crate::save::{self, SaveError, Storage};
+use crate::time::Time;
+use serde::{Deserialize, Serialize};
+use crate::{IS_WASM, TILE_SIZE, VIRTUAL_WIDTH};
 
 
 const MAX_SPEED: f32 = 300.0;
@@ -22,7 +28,10 @@ pub const JETPACK_TIME: f32 = 0.75;
 const PLAYER_W: f32 = TILE_SIZE - 6.0;
 const PLAYER_H: f32 = TILE_SIZE;
 
-#[derive(Default, Copy, Clone, Debug)]
+/// How many tiles up from where a player was saved to look for room, if they'd be stuck in the ground there.
+const SAFE_SPOT_SEARCH: i32 = 32;
+
+#[derive(Default, Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Facing {
     Left = 0,
     #[default]
@@ -40,6 +49,45 @@ pub enum Jumping {
 #[derive(Component)]
 pub struct PlayerTag;
 
+/// What's kept of the player between games, saved along with the world.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SavedPlayer {
+    pub pos: Vec2,
+    pub speed: Vec2,
+    pub facing: Facing,
+    pub selected_item: u8,
+    pub inventory: [TileId; 4],
+    /// Where the camera was looking.
+    pub camera_target: Vec2,
+}
+
+impl SavedPlayer {
+    pub fn new(player: &Player, collider: &Collider, camera_target: Vec2) -> SavedPlayer {
+        SavedPlayer {
+            pos: collider.pos,
+            speed: player.speed,
+            facing: player.facing,
+            selected_item: player.selected_item,
+            inventory: *player.inventory,
+            camera_target,
+        }
+    }
+
+    /// The player saved with the world in `slot`.
+    pub fn load(storage: &Storage, slot: &str) -> Result<SavedPlayer, SaveError> {
+        let data = storage.lock()?.get(&player_key(slot)).ok_or(SaveError::Missing)?;
+        let record = save::decode(&data)?;
+        match record.version {
+            1 => Ok(bincode::deserialize(&record.body)?),
+            version => Err(SaveError::Incompatible { version }),
+        }
+    }
+
+    pub fn save(&self, storage: &Storage, slot: &str) -> Result<(), SaveError> {
+        storage.lock()?.set(&player_key(slot), &save::encode(self)?)
+    }
+}
+
 #[derive(Component, Debug)]
 pub struct Player {
     pub speed: Vec2,
@@ -47,11 +95,20 @@ pub struct Player {
     pub size: Vec2,
     pub jumping: Jumping,
     pub selected_item: u8,
-    pub inventory: Box<[TileId; 4]>
+    pub inventory: Box<[TileId; 4]>,
+}
+
+/// Standing on the ground in the middle of the first screen.
+pub fn spawn_point(chunk_map: &ChunkMap) -> Vec2 {
+    let x = VIRTUAL_WIDTH/2.;
+    let columns = (x / TILE_SIZE) as i32..=((x + PLAYER_W - 1.) / TILE_SIZE) as i32;
+    let ground = columns.map(|column| chunk_map.surface_height(column)).min().unwrap();
+    
+    vec2(x, ground as f32 * TILE_SIZE - PLAYER_H)
 }
 
 pub fn new_player(chunk_map: &mut ChunkMap) -> (Player, Collider, crate::physics2::Actor) {
-    let position = vec2(VIRTUAL_WIDTH/2., VIRTUAL_HEIGHT/2.);
+    let position = spawn_point(chunk_map);
 
     let (actor, collider) = crate::physics2::add_actor(position, PLAYER_W as i32, PLAYER_H as i32, chunk_map);
     (
@@ -61,29 +118,66 @@ pub fn new_player(chunk_map: &mut ChunkMap) -> (Player, Collider, crate::physics
             facing: Facing::Forward,
             jumping: Jumping::Not,
             selected_item: 0,
-            inventory: Box::new([TileId::Dirt, TileId::WoodPlanks, TileId::WoodLog, TileId::GenericOre])
+            inventory: Box::new([TileId::Dirt, TileId::WoodPlanks, TileId::WoodLog, TileId::GenericOre]),
         },
         collider,
         actor
     )
     
 }
-pub fn move_player(mut v_player: Query<(&mut Player, &mut Collider), With<PlayerTag>>, mut v_phys_world: Query<&mut ChunkMap>) {
-    let (mut player, mut collider) = v_player.single_mut();
-    let mut world = v_phys_world.single_mut();
-    let world = world.as_mut();
-    // player.update(world);
+
+/// The player as they were saved. If the ground's changed so they'd be stuck in it, they're put on top of it
+/// instead, or back at the spawn point if that's too far up.
+pub fn restore_player(chunk_map: &mut ChunkMap, saved: &SavedPlayer) -> (Player, Collider, crate::physics2::Actor) {
+    let size = vec2(PLAYER_W, PLAYER_H);
+    let position = (0..=SAFE_SPOT_SEARCH)
+        .map(|tiles| saved.pos - vec2(0., tiles as f32 * TILE_SIZE))
+        .find(|&pos| chunk_map.collide(Rect::from_vecs(pos, size)) != CollisionResult::Solid)
+        .unwrap_or_else(|| spawn_point(chunk_map));
+
+    let (actor, collider) = crate::physics2::add_actor(position, PLAYER_W as i32, PLAYER_H as i32, chunk_map);
+    (
+        Player {
+            size,
+            speed: saved.speed,
+            facing: saved.facing,
+            jumping: Jumping::Not,
+            selected_item: saved.selected_item,
+            inventory: Box::new(saved.inventory),
+        },
+        collider,
+        actor
+    )
+}
+pub fn select_item(mut v_player: Query<&mut Player, With<PlayerTag>>, input: Res<InputActions>) {
+    let mut player = v_player.single_mut();
     
     let scroll_sensitivity = if IS_WASM {
         16
     } else {
         64
     };
-    player.selected_item = player.selected_item.overflowing_add_signed((mouse_wheel().1 as i8).saturating_mul(scroll_sensitivity)).0;
+    player.selected_item = player.selected_item.overflowing_add_signed((input.scroll as i8).saturating_mul(scroll_sensitivity)).0;
+    
+    let slot = player.get_inventory_index() as isize;
+    if input.just_pressed(Action::NextSlot) {
+        player.select_slot(slot + 1);
+    }
+    if input.just_pressed(Action::PrevSlot) {
+        player.select_slot(slot - 1);
+    }
+}
+
+pub fn move_player(mut v_player: Query<(&mut Player, &mut Collider), With<PlayerTag>>, mut v_phys_world: Query<&mut ChunkMap>, time: Res<Time>, input: Res<InputActions>, mut focused: EventWriter<ChunkFocused>) {
+    let (mut player, mut collider) = v_player.single_mut();
+    let mut world = v_phys_world.single_mut();
+    let world = world.as_mut();
+    let dt = time.fixed_delta();
+    
+    collider.begin_tick();
     
-    if is_key_down(KeyCode::X) {
-        // world.set_actor_position(self.collider,  vec2(VIRTUAL_WIDTH/2., VIRTUAL_HEIGHT/2.));
-        collider.pos = vec2(VIRTUAL_WIDTH/2., VIRTUAL_HEIGHT/2.);
+    if input.pressed(Action::Respawn) {
+        collider.teleport(spawn_point(world));
     }
     let pos = collider.pos;
     let width = ivec2(collider.width, collider.height).as_vec2();
@@ -97,14 +191,14 @@ pub fn move_player(mut v_player: Query<(&mut Player, &mut Collider), With<Player
         player.speed.y = 0.;
         player.jumping = Jumping::Not;
     } else {
-        player.speed.y += GRAVITY * get_frame_time();
+        player.speed.y += GRAVITY * dt;
     }
     if on_ceil {
         player.speed.y = player.speed.y.abs() / 2.;
     }
 
-    let left = is_key_down(KeyCode::A);
-    let right = is_key_down(KeyCode::D);
+    let left = input.pressed(Action::MoveLeft);
+    let right = input.pressed(Action::MoveRight);
 
     player.facing = Facing::Forward;
     player.speed.x = 0.;
@@ -117,24 +211,27 @@ pub fn move_player(mut v_player: Query<(&mut Player, &mut Collider), With<Player
         player.speed.x = WALK_SPEED;
     }
     
+    let jump_held = input.pressed(Action::Jump);
+    let jump_pressed = input.just_pressed(Action::Jump);
+    
     match player.jumping {
         Jumping::Not => {
-            if is_key_down(KeyCode::Space) && on_ground {
+            if jump_held && on_ground {
                 player.speed.y = -180.;
                 player.jumping = Jumping::Jumping;
             }
         },
         Jumping::Jumping => {
-            if is_key_pressed(KeyCode::Space) {
-                player.speed.y -= JUMP_IMPULSE * get_frame_time();
+            if jump_pressed {
+                player.speed.y -= JUMP_IMPULSE * dt;
                 player.jumping = Jumping::Jetpacking(JETPACK_TIME);
             }
         },
         Jumping::Jetpacking(t) if t <= 0.0 => { }
         Jumping::Jetpacking(time_left) => {
-            if is_key_down(KeyCode::Space) {
-                player.speed.y -= jetpack_decay_curve(time_left);
-                player.jumping = Jumping::Jetpacking(time_left - get_frame_time());
+            if jump_held {
+                player.speed.y -= jetpack_decay_curve(time_left, dt);
+                player.jumping = Jumping::Jetpacking(time_left - dt);
             }
         },
     }
@@ -142,22 +239,23 @@ pub fn move_player(mut v_player: Query<(&mut Player, &mut Collider), With<Player
     player.speed.y = player.speed.y.clamp(-MAX_SPEED, MAX_SPEED);
     player.speed.x = player.speed.x.clamp(-MAX_SPEED, MAX_SPEED);
     
-    move_v(world,  collider.as_mut(), player.speed.y * get_frame_time());
-    move_h(world, collider.as_mut(), player.speed.x * get_frame_time());
+    move_v(world,  collider.as_mut(), player.speed.y * dt);
+    move_h(world, collider.as_mut(), player.speed.x * dt);
 
     let chunk_in = WorldPos(collider.pos).to_chunk();
     if world.focus != chunk_in {
         println!("CHUNK LOAD");
         println!("{} -> {}", world.focus.0, chunk_in.0);
+        focused.send(ChunkFocused { from: world.focus, to: chunk_in });
         world.focus = chunk_in;
     }
 }
-pub fn draw_player(v_player: Query<(&Player, &Collider), With<PlayerTag>>) {
+pub fn draw_player(v_player: Query<(&Player, &Collider), With<PlayerTag>>, time: Res<Time>) {
     let (player, collider) = v_player.get_single().unwrap();
     
     dbg!(player);
     
-    let position = collider.pos;
+    let position = collider.interpolated_pos(time.alpha());
     draw_from_tile_set(11 + (player.facing as u32), position + vec2(-2.0, -1.0));
 }
 
@@ -169,8 +267,13 @@ impl Player {
     pub fn get_inventory_index(&self) -> usize {
         (self.selected_item as usize / (u8::max_value() as f32 * 0.25) as usize).min(3)
     }
+    /// Selects a hotbar slot by index, wrapping around at either end.
+    pub fn select_slot(&mut self, index: isize) {
+        let slots = self.inventory.len() as isize;
+        self.selected_item = (index.rem_euclid(slots) * 64) as u8;
+    }
 }
 
-pub fn jetpack_decay_curve(_time_left: f32) -> f32 {
-    JETPACK_IMPULSE * get_frame_time()
+pub fn jetpack_decay_curve(_time_left: f32, dt: f32) -> f32 {
+    JETPACK_IMPULSE * dt
 }
\ No newline at end of file
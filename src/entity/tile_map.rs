@@ -1,30 +1,77 @@
-use std::collections::HashMap;
-use base64::prelude::BASE64_STANDARD;
-use base64::Engine as _;
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
 use bevy_ecs::prelude::*;
 use itertools::Itertools;
 use macroquad::prelude::*;
 use serde::{Deserialize, Serialize};
-use crate::grid::Grid;
 use crate::entity::player::Player;
-use crate::physics2::CollisionResult;
-use crate::position::{ChunkPos, RectExtend, ScreenPos, WorldPos};
+use crate::entity::start_saving;
+pub use crate::entity::chunk::Chunk;
+use crate::entity::chunk::GridChunk;
+use crate::entity::player::PlayerTag;
+use crate::events::{ChunkGenerated, TileChanged};
+use crate::physics2::{Collider, CollisionResult};
+use crate::save::slots::FIRST_SLOT_KEY;
+use crate::save::background::{SaveInProgress, SaveStep};
+use crate::save::{self, SaveError, Storage, StorageBackend};
+use crate::position::{ChunkPos, RectExtend, ScreenPos, TilePos, WorldPos};
 use crate::tile::TileId;
-use crate::entity::ui::draw_from_tile_set;
-use crate::{SAVE_TIMER, TILE_SIZE};
+use crate::time::Time;
+use crate::worldgen::{self, Biome, DefaultGenerator, FlatGenerator, WorldGenerator};
+use crate::{SAVE_EDITS, SAVE_TIMER, TILE_SIZE};
 
 pub(super) fn init_map(mut commands: Commands) {
-    commands.insert_resource(SaveTimer(SAVE_TIMER))
+    commands.insert_resource(SaveTimer(SAVE_TIMER));
+    commands.init_resource::<ChunkUnloading>();
 }
 
-pub(super) fn timed_save(mut timer: ResMut<SaveTimer>, mut map: Query<&mut ChunkMap>) {
-    let map = map.get_single_mut().unwrap();
+/// Writes out and drops chunks nobody's near, once there are more in memory than [`ChunkUnloading`] allows.
+pub(super) fn unload_chunks(mut map: Query<&mut ChunkMap>, players: Query<&Collider, With<PlayerTag>>, unloading: Res<ChunkUnloading>) {
+    let Ok(mut map) = map.get_single_mut() else { return };
+    let players = players.iter().map(|collider| WorldPos(collider.pos).to_chunk()).collect_vec();
+
+    map.unload_far_from(&players, &unloading);
+}
+
+/// Starts saving everything every [`SAVE_TIMER`] seconds. Needs the whole world to find everything that's saved.
+pub(super) fn timed_save(world: &mut World) {
+    let delta = world.resource::<Time>().fixed_delta();
+    // Still writing the last one, so try again next tick
+    let saving = world.contains_resource::<SaveInProgress>();
+    let mut timer = world.resource_mut::<SaveTimer>();
     
     if timer.0 < 0.0 {
-        map.save();
-        timer.0 = SAVE_TIMER;
+        if !saving {
+            timer.0 = SAVE_TIMER;
+            start_saving(world);
+        }
     } else {
-        timer.0 -= get_frame_time()
+        timer.0 -= delta
+    }
+}
+
+/// Brings the next save forward once [`SAVE_EDITS`] tiles have been changed, rather than waiting on the timer.
+pub(super) fn save_after_edits(mut changed: EventReader<TileChanged>, mut edits: Local<u32>, mut timer: ResMut<SaveTimer>) {
+    let Some(limit) = SAVE_EDITS else { return };
+    *edits += changed.read().count() as u32;
+    if *edits >= limit {
+        *edits = 0;
+        timer.0 = -1.0;
+    }
+}
+
+/// Sends out what happened to the map during the tick. The map can't send them itself,
+/// it's changed from all over the place and has no access to the world.
+pub(super) fn send_map_events(
+    mut map: Query<&mut ChunkMap>,
+    mut tile_changed: EventWriter<TileChanged>,
+    mut chunk_generated: EventWriter<ChunkGenerated>,
+) {
+    for mut map in &mut map {
+        let events = std::mem::take(&mut map.events);
+        tile_changed.send_batch(events.tiles_changed);
+        chunk_generated.send_batch(events.chunks_generated);
     }
 }
 
@@ -45,88 +92,293 @@ pub(super) fn draw_map(mut map: Query<&mut ChunkMap>) {
 #[derive(Resource)]
 pub struct SaveTimer(pub f32);
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
-pub struct Chunk(pub Grid<TileId>);
-
-static SAVE_KEY: &str = "ChunkMap";
+/// How many chunks a [`ChunkMap`] keeps in memory.
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct ChunkUnloading {
+    /// Chunks within this many chunks of a player, across or diagonally, are never unloaded.
+    pub radius: i32,
+    /// Once more chunks than this are loaded, the ones used longest ago are unloaded,
+    /// so going back to somewhere recent doesn't have to load it again.
+    pub max_loaded: usize,
+}
 
-impl Chunk {
-    pub fn draw(&self, offset: Vec2) {
-        self.0.for_each_immut(|point, tile| {
-            if let Some(tile_index) = tile.val().sprite {
-                draw_from_tile_set(tile_index, point.as_vec2() * 16. + offset);
-            }
-        });
+impl Default for ChunkUnloading {
+    fn default() -> Self {
+        ChunkUnloading { radius: 2, max_loaded: 64 }
     }
-    pub fn dbg_draw(&self, offset: Vec2) {
-        self.0.for_each_immut(|point, tile| {
-            let tile = tile.val().collision_result();
-            
-            let color = if tile == CollisionResult::Solid { PURPLE } else { SKYBLUE } ;
-            draw_rectangle(
-                (point.x as f32 * 2.) + offset.x,
-                (point.y as f32 * 2.) + offset.y,
-                2.,
-                2.,
-                color
-            )
-        });
+}
+
+/// Where the last save of the world in `slot` that loaded is kept whole, in case the save is damaged later.
+fn backup_key(slot: &str) -> String {
+    format!("{slot}.backup")
+}
+
+/// Where a save that couldn't be loaded is moved to, when a new world is started in its place.
+fn broken_key(slot: &str) -> String {
+    format!("{slot}.broken")
+}
+
+/// Where the player in the world saved under `save_key` is saved.
+pub fn player_key(save_key: &str) -> String {
+    format!("{save_key}/player")
+}
+
+/// Where the [`crate::save::scene::Saveable`] entities in the world saved under `save_key` are saved.
+pub fn entities_key(save_key: &str) -> String {
+    format!("{save_key}/entities")
+}
+
+/// Moves the record under `key` that couldn't be read out of the way, so saving again doesn't write over it.
+/// It stays under the same save, which takes it along when it's copied aside or deleted.
+pub fn set_aside_record(storage: &Storage, key: &str) -> Result<(), SaveError> {
+    let mut storage = storage.lock()?;
+    if let Some(data) = storage.get(key) {
+        storage.set(&broken_key(key), &data)?;
+        storage.remove(key)?;
     }
+    Ok(())
 }
 
+/// Chunks are saved in square regions this many chunks a side, each under its own key,
+/// so a save only rewrites the regions with something new in them.
+const REGION_SIZE: i32 = 4;
+
 #[derive(Component, Clone, Debug, Serialize, Deserialize)]
 pub struct ChunkMap {
+    /// The chunks in memory.
     store: HashMap<ChunkPos, Chunk>,
+    /// The chunks saved in each region, which are read back from there when they're next used.
+    regions: HashMap<IVec2, HashSet<ChunkPos>>,
     pub focus: ChunkPos,
     pub tile_size: UVec2,
     pub chunk_size: UVec2,
     pub tag: u8,
+    /// Fills in chunks the first time they're asked for.
+    #[serde(with = "worldgen::saved")]
+    generator: Arc<dyn WorldGenerator>,
+    /// Where it's saved. Maps that haven't been given anywhere keep their saves in memory.
+    #[serde(skip, default = "Storage::memory")]
+    storage: Storage,
+    /// Which [`save::slots`] slot it's saved in.
+    #[serde(skip)]
+    slot: String,
+    /// Chunks changed since they were last saved. Ones that have only been generated aren't saved,
+    /// they can be generated again.
+    #[serde(skip)]
+    dirty: HashSet<ChunkPos>,
+    #[serde(skip)]
+    last_used: LastUsed,
+    #[serde(skip)]
+    events: MapEvents,
+    /// Copies of the regions read since [`ChunkMap::copy_region_reads`], for a replay.
+    #[serde(skip)]
+    regions_read: Option<SavedRegions>,
+}
+
+/// What's saved under the slot's key: everything about a [`ChunkMap`] but its chunks, which are saved by region.
+#[derive(Serialize, Deserialize)]
+struct SavedMap {
+    regions: HashMap<IVec2, HashSet<ChunkPos>>,
+    focus: ChunkPos,
+    tile_size: UVec2,
+    chunk_size: UVec2,
+    tag: u8,
+    #[serde(with = "worldgen::saved")]
+    generator: Arc<dyn WorldGenerator>,
+}
+
+/// The saved chunks in one region.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+struct Region {
+    chunks: HashMap<ChunkPos, Chunk>,
+}
+
+/// Regions a map read out of its storage, so it can be used away from there and read the same.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct SavedRegions(HashMap<IVec2, Region>);
+
+impl SavedRegions {
+    /// Adds the regions in `other` that aren't already here. A region's first read is the one that counts.
+    pub fn extend(&mut self, other: SavedRegions) {
+        for (pos, region) in other.0 {
+            self.0.entry(pos).or_insert(region);
+        }
+    }
+
+    pub fn positions(&self) -> impl Iterator<Item = IVec2> + '_ {
+        self.0.keys().copied()
+    }
+}
+
+/// When each loaded chunk was last asked for, for unloading the stalest first.
+#[derive(Clone, Debug, Default)]
+struct LastUsed {
+    clock: u64,
+    chunks: HashMap<ChunkPos, u64>,
+}
+
+/// Changes waiting for [`send_map_events`].
+#[derive(Clone, Debug, Default)]
+struct MapEvents {
+    tiles_changed: Vec<TileChanged>,
+    chunks_generated: Vec<ChunkGenerated>,
+}
+
+/// How a [`ChunkMap`] was saved before there was more than one world, every chunk in the one save.
+/// Every world was flat back then.
+#[derive(Deserialize)]
+struct UnseededChunkMap {
+    store: HashMap<ChunkPos, GridChunk>,
+    focus: ChunkPos,
+    tile_size: UVec2,
+    chunk_size: UVec2,
+    tag: u8,
+}
+
+impl From<UnseededChunkMap> for ChunkMap {
+    fn from(old: UnseededChunkMap) -> Self {
+        // Same ground as back then, so new chunks line up with the saved ones
+        let generator = Arc::new(FlatGenerator::default());
+        ChunkMap::from_old(old.store, old.focus, old.tile_size, old.chunk_size, old.tag, generator)
+    }
+}
+
+impl From<SavedMap> for ChunkMap {
+    fn from(saved: SavedMap) -> Self {
+        ChunkMap {
+            store: HashMap::new(),
+            regions: saved.regions,
+            focus: saved.focus,
+            tile_size: saved.tile_size,
+            chunk_size: saved.chunk_size,
+            tag: saved.tag,
+            generator: saved.generator,
+            storage: Storage::memory(),
+            slot: String::new(),
+            dirty: HashSet::new(),
+            last_used: LastUsed::default(),
+            events: MapEvents::default(),
+            regions_read: None,
+        }
+    }
 }
 
 impl ChunkMap {
     pub fn get(&mut self, chunk_index: ChunkPos) -> &Chunk {
-       self.get_mut(chunk_index)
+        self.load_or_generate(chunk_index)
     }
     
+    /// The chunk at `chunk_index`, which will be saved again whether it's changed or not.
     pub fn get_mut(&mut self, chunk_index: ChunkPos) -> &mut Chunk {
-        let chunk_size = ScreenPos::screen().0 / TILE_SIZE;
+        self.dirty.insert(chunk_index);
+        self.load_or_generate(chunk_index)
+    }
+
+    fn load_or_generate(&mut self, chunk_index: ChunkPos) -> &mut Chunk {
+        self.last_used.clock += 1;
+        self.last_used.chunks.insert(chunk_index, self.last_used.clock);
+
+        // A region only has the chunks that have been changed, the rest are generated as usual
+        let region = region_of(chunk_index);
+        let saved = self.regions.get(&region).is_some_and(|chunks| chunks.contains(&chunk_index));
+        if saved && !self.store.contains_key(&chunk_index) {
+            self.read_region(region);
+        }
+
+        let (generator, chunk_size, events) = (&self.generator, self.chunk_size, &mut self.events);
         self.store.entry(chunk_index).or_insert_with(|| {
-            match chunk_index.0.y {
-                0 => {
-                    Chunk(Grid::new_filled(
-                        chunk_size.x as usize,
-                        chunk_size.y as usize,
-                        |point| if point.y > 7 { TileId::Dirt } else { TileId::Air },
-                        TileId::default()
-                    ))
-                }
-                1..=i32::MAX => {
-                    Chunk(Grid::new_filled(
-                        chunk_size.x as usize,
-                        chunk_size.y as usize,
-                        |_| TileId::Dirt,
-                        TileId::default()
-                    ))
-                }
-                i32::MIN..=-1 => {
-                    Chunk(Grid::new_filled(
-                        chunk_size.x as usize,
-                        chunk_size.y as usize,
-                        |_| TileId::Air,
-                        TileId::default()
-                    ))
-                }
-            }
+            events.chunks_generated.push(ChunkGenerated(chunk_index));
+            generator.generate_chunk(chunk_index, chunk_size)
         })
     }
 
-    pub fn place_tile(&mut self, rect: Rect, pos: WorldPos, tile: TileId) {
+    /// Brings every chunk saved in the region at `pos` into memory, so it's read once rather than once a chunk.
+    /// Chunks already in memory are newer than the saved ones.
+    fn read_region(&mut self, pos: IVec2) {
+        let region = match load_region(&self.storage, &self.slot, pos) {
+            Ok(region) => region,
+            Err(e) => {
+                println!("Couldn't load region {pos}, generating its chunks again: {e}");
+                return;
+            }
+        };
+        if let Some(read) = &mut self.regions_read {
+            read.0.entry(pos).or_insert_with(|| region.clone());
+        }
+        for (chunk_pos, chunk) in region.chunks {
+            if chunk.size() != self.chunk_size {
+                println!("Saved chunk {:?} is {}, not {}, generating it again", chunk_pos.0, chunk.size(), self.chunk_size);
+                continue;
+            }
+            self.store.entry(chunk_pos).or_insert(chunk);
+        }
+    }
+
+    /// Writes out and drops the chunks used longest ago until there are no more than `unloading.max_loaded`,
+    /// leaving alone anything within `unloading.radius` of `players`.
+    pub fn unload_far_from(&mut self, players: &[ChunkPos], unloading: &ChunkUnloading) {
+        let excess = self.store.len().saturating_sub(unloading.max_loaded);
+        if excess == 0 {
+            return;
+        }
+
+        let near_player = |pos: &ChunkPos| players.iter()
+            .any(|player| (pos.0 - player.0).abs().max_element() <= unloading.radius);
+        let stalest = self.store.keys()
+            .filter(|pos| !near_player(pos))
+            .copied()
+            .sorted_by_key(|pos| self.last_used.chunks.get(pos).copied().unwrap_or(0))
+            .take(excess)
+            .collect_vec();
+
+        // Chunks that haven't changed since they were saved can just be dropped
+        let changed = stalest.iter().copied().filter(|pos| self.dirty.contains(pos)).collect_vec();
+        if let Err(e) = self.write_chunks(changed) {
+            // Better to run out of memory eventually than lose them
+            println!("Couldn't unload chunks: {e}");
+            return;
+        }
+        for pos in stalest {
+            self.store.remove(&pos);
+            self.last_used.chunks.remove(&pos);
+        }
+    }
+
+    /// Saves `chunks` into their regions, keeping whatever else is saved there.
+    fn write_chunks(&mut self, chunks: Vec<ChunkPos>) -> Result<(), SaveError> {
+        for (region_pos, chunks) in chunks.into_iter().into_group_map_by(|pos| region_of(*pos)) {
+            let saved = chunks.iter().map(|pos| (*pos, self.store[pos].clone())).collect_vec();
+            let existing = self.regions.contains_key(&region_pos);
+            write_region(&self.storage, &self.slot, region_pos, existing, saved)?;
+            for pos in chunks {
+                self.regions.entry(region_pos).or_default().insert(pos);
+                self.dirty.remove(&pos);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn generator(&self) -> &dyn WorldGenerator {
+        &*self.generator
+    }
+
+    /// The row of the topmost solid tile in column `x` when it was generated, in world tiles.
+    pub fn surface_height(&self, x: i32) -> i32 {
+        self.generator.surface_height(x)
+    }
+
+    /// The biome `pos` is in, as the generator decides it.
+    pub fn biome_at(&self, pos: WorldPos) -> Biome {
+        self.generator.biome_at(pos.to_tile().0.x.floor() as i32)
+    }
+
+    /// Sets the tile under `pos`, unless it would overlap `rect`. Returns whether the tile was placed.
+    pub fn place_tile(&mut self, rect: Rect, pos: WorldPos, tile: TileId) -> bool {
         
         let tile_rect = Rect::from_vecs(pos.snap().0, Vec2::splat(16.0));
         
         if rect.intersect(tile_rect).is_some_and(|rect| rect.size().min_element() != 0.0) {
-            draw_rectangle(tile_rect.x, tile_rect.y, tile_rect.w, tile_rect.h, RED);
-            return;
+            return false;
         }
         
         let chunk_inside = pos.to_chunk();
@@ -138,44 +390,247 @@ impl ChunkMap {
         );
         let chunk_inside = self.get_mut(chunk_inside);
 
-        let pos = WorldPos(position_in_chunk).to_tile().0.as_uvec2();
+        let pos_in_chunk = WorldPos(position_in_chunk).to_tile().0.as_uvec2();
 
-        chunk_inside.0[pos] = tile;
+        let from = chunk_inside.set(pos_in_chunk, tile);
+        if from != tile {
+            self.events.tiles_changed.push(TileChanged {
+                pos: TilePos(pos.to_tile().0.floor()),
+                from,
+                to: tile,
+            });
+        }
+        true
     }
 
 }
 
 
 impl ChunkMap {
-    pub fn new() -> ChunkMap {
+    /// A world of the [`DefaultGenerator`]'s terrain.
+    pub fn new(seed: u32) -> ChunkMap {
+        ChunkMap::with_generator(DefaultGenerator::new(seed))
+    }
+
+    pub fn with_generator(generator: impl WorldGenerator + 'static) -> ChunkMap {
         let chunk_size = (ScreenPos::screen().0 / TILE_SIZE).as_uvec2();
         let chunks = HashMap::<ChunkPos, Chunk>::new();
 
         ChunkMap {
             store: chunks, 
+            regions: HashMap::new(),
             focus: ChunkPos(ivec2(0,0)),
             tile_size: UVec2::splat(TILE_SIZE as u32),
             chunk_size,
-            tag: 0
+            tag: 0,
+            generator: Arc::new(generator),
+            storage: Storage::memory(),
+            slot: FIRST_SLOT_KEY.to_owned(),
+            dirty: HashSet::new(),
+            last_used: LastUsed::default(),
+            events: MapEvents::default(),
+            regions_read: None,
         }
     }
     
-    pub fn save(&self) {
+    /// A map from before regions, with every chunk it had needing saving again.
+    fn from_old(
+        store: HashMap<ChunkPos, GridChunk>,
+        focus: ChunkPos,
+        tile_size: UVec2,
+        chunk_size: UVec2,
+        tag: u8,
+        generator: Arc<dyn WorldGenerator>,
+    ) -> ChunkMap {
+        ChunkMap {
+            dirty: store.keys().copied().collect(),
+            store: store.into_iter().map(|(pos, chunk)| (pos, chunk.into())).collect(),
+            regions: HashMap::new(),
+            focus,
+            tile_size,
+            chunk_size,
+            tag,
+            generator,
+            storage: Storage::memory(),
+            slot: String::new(),
+            last_used: LastUsed::default(),
+            events: MapEvents::default(),
+            regions_read: None,
+        }
+    }
+
+    pub fn storage(&self) -> &Storage {
+        &self.storage
+    }
+
+    pub fn slot(&self) -> &str {
+        &self.slot
+    }
+
+    /// The same map, saved in `slot` of `storage` from now on.
+    pub fn saved_in(mut self, storage: &Storage, slot: impl Into<String>) -> ChunkMap {
+        self.storage = storage.clone();
+        self.slot = slot.into();
+        self
+    }
+
+    /// Starts keeping a copy of every region read from here on, until they're taken with [`ChunkMap::take_region_reads`].
+    pub fn copy_region_reads(&mut self) {
+        self.regions_read = Some(SavedRegions::default());
+    }
+
+    /// The regions read since the last time they were taken.
+    pub fn take_region_reads(&mut self) -> SavedRegions {
+        self.regions_read.as_mut().map(std::mem::take).unwrap_or_default()
+    }
+
+    /// The same map, saved in memory of its own with `saved` as its regions.
+    pub fn with_saved_regions(self, saved: &SavedRegions) -> ChunkMap {
+        let storage = Storage::memory();
+        for (&pos, region) in &saved.0 {
+            // Memory doesn't run out of room
+            save_region(&storage, &self.slot, pos, region).expect("Couldn't save to memory");
+        }
+        ChunkMap { storage, regions_read: None, ..self }
+    }
+
+    /// Writes out the chunks that have changed since the last save, and everything else about the map.
+    pub fn save(&mut self) -> Result<(), SaveError> {
+        let result = self.save_steps().into_iter().try_for_each(|step| step());
+        if result.is_err() {
+            self.save_failed();
+        }
+        result
+    }
+
+    /// What [`ChunkMap::save`] writes, as steps that can be run later and off the main thread. The changed
+    /// chunks are copied out and counted as saved from here on, so they can keep changing in the meantime.
+    pub fn save_steps(&mut self) -> Vec<SaveStep> {
         println!("Save");
-        let data = bincode::serialize(&self).expect("Serde Bincode failure");
-        let data = BASE64_STANDARD.encode(data);
-        let storage = &mut quad_storage::STORAGE.lock().expect("Storage lock fail");
-        storage.set(SAVE_KEY, &data);
+        let mut steps: Vec<SaveStep> = Vec::new();
+        let changed = std::mem::take(&mut self.dirty);
+        for (region_pos, chunks) in changed.into_iter().into_group_map_by(|pos| region_of(*pos)) {
+            let existing = self.regions.contains_key(&region_pos);
+            self.regions.entry(region_pos).or_default().extend(chunks.iter().copied());
+            let saved = chunks.into_iter().map(|pos| (pos, self.store[&pos].clone())).collect_vec();
+            let (storage, slot) = (self.storage.clone(), self.slot.clone());
+            steps.push(Box::new(move || write_region(&storage, &slot, region_pos, existing, saved)));
+        }
+
+        let saved = SavedMap {
+            regions: self.regions.clone(),
+            focus: self.focus,
+            tile_size: self.tile_size,
+            chunk_size: self.chunk_size,
+            tag: self.tag,
+            generator: self.generator.clone(),
+        };
+        let (storage, slot) = (self.storage.clone(), self.slot.clone());
+        steps.push(Box::new(move || {
+            storage.lock()?.set(&slot, &save::encode(&saved)?)?;
+            save::slots::played(&storage, &slot)
+        }));
+        steps
+    }
+
+    /// Counts every chunk in memory as changed, after a save that might not have written them.
+    pub fn save_failed(&mut self) {
+        self.dirty.extend(self.store.keys().copied());
     }
     
-    pub fn load() -> Option<ChunkMap> {
+    /// Loads the map saved in `slot`. Once it's loaded, and every region in it can be read,
+    /// it's copied over the backup, since it's known to be good.
+    pub fn load(storage: &Storage, slot: &str) -> Result<ChunkMap, SaveError> {
         println!("Load");
-        let storage = &mut quad_storage::STORAGE.lock().expect("Storage lock fail");
-        
-        let data = BASE64_STANDARD.decode(storage.get(SAVE_KEY)?).ok()?;
-        let world: ChunkMap = bincode::deserialize(&data[..]).ok()?;
+        let map = read_saved(storage, slot)?;
+        for &region in map.regions.keys() {
+            load_region(storage, slot, region).map_err(|e| match e {
+                SaveError::Missing => SaveError::Corrupt(format!("region {region} is missing")),
+                e => e,
+            })?;
+        }
+
+        let backup = backup_key(slot);
+        let mut storage = storage.lock()?;
+        for key in keys_under(&*storage, &backup) {
+            storage.remove(&key)?;
+        }
+        copy_save(&mut *storage, slot, &backup, map.regions.keys().copied())?;
+
+        Ok(map)
+    }
+
+    pub fn has_backup(storage: &Storage, slot: &str) -> bool {
+        storage.lock().is_ok_and(|storage| storage.get(&backup_key(slot)).is_some())
+    }
+
+    /// Puts the backup of `slot` back in place of its save, if it can be read.
+    pub fn restore_backup(storage: &Storage, slot: &str) -> Result<(), SaveError> {
+        let backup = read_saved(storage, &backup_key(slot))?;
+
+        copy_save(&mut *storage.lock()?, &backup_key(slot), slot, backup.regions.keys().copied())
+    }
+
+    /// Moves a save that couldn't be loaded out of the way, so a new world can be saved without losing it.
+    /// Whatever was set aside before is replaced.
+    pub fn set_aside_save(storage: &Storage, slot: &str) -> Result<(), SaveError> {
+        let mut storage = storage.lock()?;
+        let broken = broken_key(slot);
+        for key in keys_under(&*storage, &broken) {
+            storage.remove(&key)?;
+        }
+        // The regions and everything else go too, not knowing what the save says it has
+        for key in keys_under(&*storage, slot) {
+            let Some(data) = storage.get(&key) else { continue };
+            storage.set(&format!("{broken}{}", &key[slot.len()..]), &data)?;
+            storage.remove(&key)?;
+        }
+        Ok(())
+    }
+
+    /// Copies the map saved in `from` into `to`, upgrading it to the current layout on the way.
+    pub fn copy_saved(storage: &Storage, from: &str, to: &str) -> Result<(), SaveError> {
+        let map = read_saved(storage, from)?;
+        copy_save(&mut *storage.lock()?, from, to, map.regions.keys().copied())?;
+
+        // Older layouts had chunks outside regions, which reading brought into memory to be saved
+        map.saved_in(storage, to).save()
+    }
+
+    /// Removes everything saved in `slot`: the map, its regions, its backup and anything set aside.
+    pub fn delete_saved(storage: &Storage, slot: &str) -> Result<(), SaveError> {
+        let mut storage = storage.lock()?;
+        for save_key in [slot.to_owned(), backup_key(slot), broken_key(slot)] {
+            for key in keys_under(&*storage, &save_key) {
+                storage.remove(&key)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Roughly how much storage the map saved in `slot` takes up, in bytes, not counting its backup.
+    pub fn saved_size(storage: &Storage, slot: &str) -> usize {
+        let Ok(storage) = storage.lock() else { return 0 };
+        let Some(data) = storage.get(slot) else { return 0 };
+        let regions = save::decode(&data)
+            .and_then(|record| read_regions(&record))
+            .unwrap_or_default();
+
+        data.len() + regions.into_iter()
+            .filter_map(|region| storage.get(&region_key(slot, region)))
+            .map(|region| region.len())
+            .sum::<usize>()
+    }
+    
+    /// Feeds every loaded chunk into `state`, in a stable order, for comparing worlds.
+    pub fn hash_state(&self, state: &mut impl Hasher) {
+        let mut chunks = self.store.iter().collect_vec();
+        chunks.sort_by_key(|(pos, _)| (pos.0.x, pos.0.y));
         
-        Some(world)
+        for (pos, chunk) in chunks {
+            pos.hash(state);
+            chunk.tiles().for_each(|(_, tile)| tile.hash(state));
+        }
     }
     
     pub fn focused(&mut self) -> &Chunk {
@@ -215,9 +670,9 @@ impl ChunkMap {
             );
             let chunk_inside = self.get(chunk_inside);
 
-            let tile = chunk_inside.0.get(
+            let tile = chunk_inside.get(
                 position_in_chunk.floor().as_uvec2() / 16
-            ).unwrap().to_owned().val().collision_result();
+            ).unwrap().val().collision_result();
 
             tile
         };
@@ -265,9 +720,98 @@ impl ChunkMap {
     }
 }
 
+/// Reads the map saved under `key` in `storage`, in whichever layout it was saved in.
+fn read_saved(storage: &Storage, key: &str) -> Result<ChunkMap, SaveError> {
+    let data = storage.lock()?.get(key).ok_or(SaveError::Missing)?;
+    let map = read_map(save::decode(&data)?)?;
+    Ok(map.saved_in(storage, key))
+}
+
+/// Reads a map saved by `record.version` of the game, from whichever layout it was saved in.
+fn read_map(record: save::Record) -> Result<ChunkMap, SaveError> {
+    match record.version {
+        0 => Ok(bincode::deserialize::<UnseededChunkMap>(&record.body).map(ChunkMap::from)?),
+        1 => Ok(bincode::deserialize::<SavedMap>(&record.body).map(ChunkMap::from)?),
+        version => Err(SaveError::Incompatible { version }),
+    }
+}
+
+/// Every key belonging to the save under `save_key`: its own, and the ones under it, like its regions.
+fn keys_under(storage: &dyn StorageBackend, save_key: &str) -> Vec<String> {
+    storage.keys().into_iter()
+        .filter(|key| key.strip_prefix(save_key).is_some_and(|rest| rest.is_empty() || rest.starts_with('/')))
+        .collect()
+}
+
+/// The regions a saved map has chunks in, without loading any of them.
+fn read_regions(record: &save::Record) -> Result<HashSet<IVec2>, SaveError> {
+    match record.version {
+        // Saved with every chunk in it
+        0 => Ok(HashSet::new()),
+        1 => Ok(bincode::deserialize::<SavedMap>(&record.body)?.regions.into_keys().collect()),
+        version => Err(SaveError::Incompatible { version }),
+    }
+}
+
+/// Copies a whole save from under `from` to under `to`: the map, the player, the other entities,
+/// and the regions it has chunks in.
+fn copy_save(storage: &mut dyn StorageBackend, from: &str, to: &str, regions: impl IntoIterator<Item = IVec2>) -> Result<(), SaveError> {
+    let others = [(from.to_owned(), to.to_owned()), (player_key(from), player_key(to)), (entities_key(from), entities_key(to))];
+    for key in others.into_iter()
+        .chain(regions.into_iter().map(|region| (region_key(from, region), region_key(to, region))))
+    {
+        if let Some(data) = storage.get(&key.0) {
+            storage.set(&key.1, &data)?;
+        }
+    }
+    Ok(())
+}
+
+fn region_of(chunk: ChunkPos) -> IVec2 {
+    chunk.0.div_euclid(IVec2::splat(REGION_SIZE))
+}
+
+/// Where region `region` of the save under `save_key` goes.
+fn region_key(save_key: &str, region: IVec2) -> String {
+    format!("{save_key}/region/{},{}", region.x, region.y)
+}
+
+/// Saves `chunks` into the region at `pos`. If the region was `existing`, whatever else is saved in it is kept.
+fn write_region(storage: &Storage, save_key: &str, pos: IVec2, existing: bool, chunks: Vec<(ChunkPos, Chunk)>) -> Result<(), SaveError> {
+    let mut region = match existing {
+        true => match load_region(storage, save_key, pos) {
+            Ok(region) => region,
+            Err(SaveError::Missing) => Region::default(),
+            // What else was in it is kept out of the way, rather than lost under the new chunks
+            Err(e) => {
+                println!("Couldn't load region {pos}, setting it aside: {e}");
+                set_aside_record(storage, &region_key(save_key, pos))?;
+                Region::default()
+            }
+        },
+        false => Region::default(),
+    };
+    region.chunks.extend(chunks);
+    save_region(storage, save_key, pos, &region)
+}
+
+fn save_region(storage: &Storage, save_key: &str, pos: IVec2, region: &Region) -> Result<(), SaveError> {
+    storage.lock()?.set(&region_key(save_key, pos), &save::encode(region)?)
+}
+
+fn load_region(storage: &Storage, save_key: &str, pos: IVec2) -> Result<Region, SaveError> {
+    let data = storage.lock()?.get(&region_key(save_key, pos)).ok_or(SaveError::Missing)?;
+    let record = save::decode(&data)?;
+    match record.version {
+        1 => Ok(bincode::deserialize(&record.body)?),
+        version => Err(SaveError::Incompatible { version }),
+    }
+}
+
+/// A world with seed 0, for when it has to be the same every time.
 impl Default for ChunkMap {
     fn default() -> Self {
-        ChunkMap::new()
+        ChunkMap::new(0)
     }
 }
 
@@ -279,3 +823,145 @@ pub fn wrap_around_vec_in_rect(rect: Rect, vec: Vec2) -> Vec2 {
     vec.rem_euclid(rect.size())
 }
 
+#[cfg(test)]
+mod tests {
+    use base64::prelude::BASE64_STANDARD;
+    use base64::Engine as _;
+    use crate::grid::Grid;
+    use super::*;
+
+    #[test]
+    fn old_saves_still_load() {
+        let size = UVec2::new(16, 14);
+        let chunk = FlatGenerator::default().generate_chunk(ChunkPos(ivec2(0, 0)), size);
+        // Chunks were saved tile by tile back then
+        let grid = Grid::new_filled(16, 14, |pos| chunk.get(pos).unwrap(), TileId::Air);
+        let store = HashMap::from([(ChunkPos(ivec2(0, 0)), grid)]);
+        let header = (ChunkPos(ivec2(0, 0)), UVec2::splat(16), size, 0u8);
+
+        // Without a header
+        let unseeded = bincode::serialize(&(&store, header)).unwrap();
+        let mut map = read_map(save::decode(&BASE64_STANDARD.encode(unseeded)).unwrap()).unwrap();
+        assert_eq!(map.generator().name(), "flat");
+        assert_eq!(map.get(ChunkPos(ivec2(0, 0))), &chunk);
+        // Old saves' chunks go into regions on the next save
+        assert!(map.dirty.contains(&ChunkPos(ivec2(0, 0))));
+
+        let saved = SavedMap {
+            regions: HashMap::from([(ivec2(1, 2), HashSet::from([ChunkPos(ivec2(4, 8))]))]),
+            focus: ChunkPos(ivec2(0, 0)),
+            tile_size: UVec2::splat(16),
+            chunk_size: size,
+            tag: 0,
+            generator: Arc::new(FlatGenerator::default()),
+        };
+        let map = read_map(save::decode(&save::encode(&saved).unwrap()).unwrap()).unwrap();
+        assert_eq!(map.regions, saved.regions);
+
+        // Nothing can be made of a save from the future
+        let future = save::Record { version: save::VERSION + 1, body: bincode::serialize(&saved).unwrap() };
+        assert!(matches!(read_map(future), Err(SaveError::Incompatible { .. })));
+    }
+
+    #[test]
+    fn saves_come_back_the_same() -> Result<(), SaveError> {
+        let storage = Storage::memory();
+        let slot = "World/0";
+        let mut map = ChunkMap::with_generator(FlatGenerator::default()).saved_in(&storage, slot);
+
+        // Far enough apart to be in different regions, and one of them unloaded before saving
+        let (near, far) = (ChunkPos(ivec2(0, 0)), ChunkPos(ivec2(REGION_SIZE * 2, 1)));
+        map.get_mut(near).set(uvec2(3, 4), TileId::GoldOre);
+        map.get_mut(far).set(uvec2(5, 6), TileId::WoodPlanks);
+        map.unload_far_from(&[near], &ChunkUnloading { radius: 0, max_loaded: 1 });
+        assert!(!map.store.contains_key(&far));
+        map.save()?;
+
+        let mut loaded = ChunkMap::load(&storage, slot)?;
+        assert_eq!(loaded.get(near).get(uvec2(3, 4)), Some(TileId::GoldOre));
+        assert_eq!(loaded.get(far).get(uvec2(5, 6)), Some(TileId::WoodPlanks));
+        assert!(loaded.dirty.is_empty());
+
+        // Loading kept a backup, which is what comes back once the save is damaged
+        storage.lock()?.set(slot, "not a save")?;
+        assert!(matches!(ChunkMap::load(&storage, slot), Err(SaveError::Corrupt(_))));
+        assert!(ChunkMap::has_backup(&storage, slot));
+        ChunkMap::restore_backup(&storage, slot)?;
+        let mut restored = ChunkMap::load(&storage, slot)?;
+        assert_eq!(restored.get(far).get(uvec2(5, 6)), Some(TileId::WoodPlanks));
+        Ok(())
+    }
+
+    #[test]
+    fn only_changed_chunks_are_saved() -> Result<(), SaveError> {
+        let storage = Storage::memory();
+        let mut map = ChunkMap::with_generator(FlatGenerator::default()).saved_in(&storage, "World/0");
+        let (a, b, untouched) = (ChunkPos(ivec2(0, 0)), ChunkPos(ivec2(1, 0)), ChunkPos(ivec2(2, 0)));
+        map.get(untouched);
+        map.get_mut(a).set(uvec2(1, 1), TileId::GoldOre);
+        map.get_mut(b).set(uvec2(2, 2), TileId::GoldOre);
+        map.save()?;
+        assert_eq!(map.regions[&region_of(a)], HashSet::from([a, b]));
+
+        // Generated chunks don't need the region, and reading it for one brings in the rest of it
+        let mut loaded = ChunkMap::load(&storage, "World/0")?;
+        loaded.get(untouched);
+        assert!(!loaded.store.contains_key(&a));
+        assert_eq!(loaded.get(a).get(uvec2(1, 1)), Some(TileId::GoldOre));
+        assert!(loaded.store.contains_key(&b));
+        assert!(loaded.dirty.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn damaged_regions_arent_saved_over() -> Result<(), SaveError> {
+        let storage = Storage::memory();
+        let slot = "World/0";
+        let mut map = ChunkMap::with_generator(FlatGenerator::default()).saved_in(&storage, slot);
+        let (a, b) = (ChunkPos(ivec2(0, 0)), ChunkPos(ivec2(1, 0)));
+        map.get_mut(a).set(uvec2(1, 1), TileId::GoldOre);
+        map.save()?;
+        ChunkMap::load(&storage, slot)?;
+
+        // The backup is only replaced by a save that loads whole
+        let region = region_key(slot, region_of(a));
+        storage.lock()?.set(&region, "not a region")?;
+        assert!(matches!(ChunkMap::load(&storage, slot), Err(SaveError::Corrupt(_))));
+        let mut backup = read_saved(&storage, &backup_key(slot))?;
+        assert_eq!(backup.get(a).get(uvec2(1, 1)), Some(TileId::GoldOre));
+
+        // And saving next to it keeps it aside
+        map.get_mut(b).set(uvec2(2, 2), TileId::GoldOre);
+        map.save()?;
+        assert_eq!(storage.lock()?.get(&broken_key(&region)).as_deref(), Some("not a region"));
+        Ok(())
+    }
+
+    #[test]
+    fn set_aside_saves_keep_everything() -> Result<(), SaveError> {
+        let storage = Storage::memory();
+        let slot = "World/0";
+        let mut map = ChunkMap::with_generator(FlatGenerator::default()).saved_in(&storage, slot);
+        map.get_mut(ChunkPos(ivec2(0, 0))).set(uvec2(3, 4), TileId::GoldOre);
+        map.save()?;
+        storage.lock()?.set(&player_key(slot), "the player")?;
+        // Unreadable, so kept out of the way of the next save
+        storage.lock()?.set(&entities_key(slot), "not entities")?;
+        set_aside_record(&storage, &entities_key(slot))?;
+        assert_eq!(storage.lock()?.get(&entities_key(slot)), None);
+
+        // Nothing's left where the new world goes, and it can all be brought back from where it went
+        ChunkMap::set_aside_save(&storage, slot)?;
+        assert!(keys_under(&*storage.lock()?, slot).is_empty());
+        ChunkMap::copy_saved(&storage, &broken_key(slot), "World/1")?;
+        let mut restored = ChunkMap::load(&storage, "World/1")?;
+        assert_eq!(restored.get(ChunkPos(ivec2(0, 0))).get(uvec2(3, 4)), Some(TileId::GoldOre));
+        assert_eq!(storage.lock()?.get(&player_key("World/1")).as_deref(), Some("the player"));
+        let set_aside = broken_key(&entities_key(&broken_key(slot)));
+        assert_eq!(storage.lock()?.get(&set_aside).as_deref(), Some("not entities"));
+
+        ChunkMap::delete_saved(&storage, slot)?;
+        assert!(keys_under(&*storage.lock()?, &broken_key(slot)).is_empty());
+        Ok(())
+    }
+}
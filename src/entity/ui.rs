@@ -2,15 +2,21 @@ use crate::physics2::Collider;
 use std::f32::consts::PI;
 use bevy_ecs::prelude::*;
 use macroquad::prelude::*;
-use crate::{draw_bordered_rect, DEFAULT_FONT, SAVE_TIMER, TILE_SET, TILE_SIZE, VIRTUAL_HEIGHT, VIRTUAL_WIDTH};
+use crate::{draw_bordered_rect, DEFAULT_FONT, TILE_SET, TILE_SIZE, VIRTUAL_HEIGHT, VIRTUAL_WIDTH};
 use crate::entity::player::{JETPACK_TIME, Jumping, Player};
-use crate::position::{RectExtend, WorldPos};
+use crate::events::WorldSaved;
+use crate::input::touch::{TouchControls, JOYSTICK_CENTRE, JOYSTICK_RADIUS, JUMP_CENTRE, JUMP_RADIUS, PAUSE_CENTRE, PAUSE_RADIUS};
+use crate::position::{RectExtend, ScreenPos, WorldPos};
+use crate::tile::TileId;
+use crate::time::Time;
 
 use super::camera::GameCamera;
 use super::player::PlayerTag;
-use super::tile_map::SaveTimer;
 
 const UI_WIDTH: f32 = 87.0;
+const UI_MARGIN: f32 = 2.0;
+/// How long "Saved." fades out for after a save.
+const SAVED_TOAST_TIME: f32 = 2.0;
 
 pub const COLOR_HIGHLIGHT: u32 = 0xf93f8d;
 pub const COLOR_BASE: u32 = 0x550b39;
@@ -26,17 +32,29 @@ pub(super) fn init_ui(mut commands: Commands) {
 #[derive(Component, Default)]
 pub struct Ui;
 
+/// Where the hotbar sits, in screen space.
+pub fn hotbar_rect() -> Rect {
+    Rect::new(
+        UI_MARGIN,
+        VIRTUAL_HEIGHT - 24.0 - UI_MARGIN,
+        UI_WIDTH,
+        24.0
+    )
+}
+
 pub(super) fn draw_ui(
     mut v_camera: Query<&GameCamera>, 
     mut v_player: Query<(&Player, &Collider), With<PlayerTag>>,
-    save_timer: Res<SaveTimer>
+    mut saved: EventReader<WorldSaved>,
+    time: Res<Time>,
+    mut saved_at: Local<Option<f64>>,
 ) {
     let camera2d = &v_camera.get_single_mut().unwrap().0;
     let (player, collider) = v_player.get_single_mut().unwrap();
     
     let font = &*DEFAULT_FONT;
     
-    let margin = 2.0;
+    let margin = UI_MARGIN;
 
     let base_ui_rect = Rect::new(
         camera2d.screen_to_world(vec2(0., 0.)).x,
@@ -45,12 +63,7 @@ pub(super) fn draw_ui(
         VIRTUAL_HEIGHT
     );
 
-    let hotbar_rect = Rect::new(
-        base_ui_rect.left() + margin,
-        base_ui_rect.bottom() - 24.0 - margin,
-        UI_WIDTH,
-        24.0
-    );
+    let hotbar_rect = hotbar_rect().offset(base_ui_rect.point());
     
     draw_jump_velocity_bar(base_ui_rect, margin, hotbar_rect.h, player);
 
@@ -74,7 +87,7 @@ pub(super) fn draw_ui(
             Color::from_hex(border_color),
             Color::from_hex(COLOR_BORDER)
         );
-        draw_from_tile_set(player.inventory[i-1].val().sprite.unwrap(),
+        draw_tile(player.inventory[i-1],
         hotbar_rect.point() + 3. + vec2((i-1) as f32 * 21. , 0.) + vec2(1., 1.)
         )
     }
@@ -92,9 +105,13 @@ pub(super) fn draw_ui(
         ..Default::default()
     });
     
-    if (SAVE_TIMER - 2.0..SAVE_TIMER).contains(&save_timer.0) {
+    if saved.read().count() > 0 {
+        *saved_at = Some(time.elapsed());
+    }
+    let since_saved = saved_at.map(|at| (time.elapsed() - at) as f32);
+    if let Some(since_saved) = since_saved.filter(|since| *since < SAVED_TOAST_TIME) {
         let mut color = Color::from_hex(COLOR_HIGHLIGHT);
-        color.a = ease_out(SAVE_TIMER - save_timer.0, 2.0);
+        color.a = ease_out(since_saved, SAVED_TOAST_TIME);
         draw_text_ex(
             "Saved.", 
             base_ui_rect.right() - 32.0, 
@@ -110,6 +127,41 @@ pub(super) fn draw_ui(
 
 }
 
+pub(super) fn draw_touch_controls(touch: Res<TouchControls>, v_camera: Query<&GameCamera>) {
+    if !touch.active {
+        return;
+    }
+    let camera = &v_camera.single().0;
+    let joystick = ScreenPos(JOYSTICK_CENTRE).to_world(camera).0;
+    let knob = joystick + touch.knob();
+    let jump = ScreenPos(JUMP_CENTRE).to_world(camera).0;
+    let pause = ScreenPos(PAUSE_CENTRE).to_world(camera).0;
+    
+    let mut base = Color::from_hex(COLOR_BASE);
+    base.a = 0.5;
+    
+    draw_circle(joystick.x, joystick.y, JOYSTICK_RADIUS, base);
+    draw_circle_lines(joystick.x, joystick.y, JOYSTICK_RADIUS, 1.0, Color::from_hex(COLOR_BORDER));
+    draw_circle(knob.x, knob.y, JOYSTICK_RADIUS / 2.0, Color::from_hex(COLOR_SURFACE));
+    draw_circle_lines(knob.x, knob.y, JOYSTICK_RADIUS / 2.0, 1.0, Color::from_hex(COLOR_HIGHLIGHT));
+    
+    let jump_color = if touch.jump_held() { COLOR_HIGHLIGHT } else { COLOR_SURFACE };
+    draw_circle(jump.x, jump.y, JUMP_RADIUS, base);
+    draw_circle_lines(jump.x, jump.y, JUMP_RADIUS, 1.0, Color::from_hex(jump_color));
+    draw_triangle(
+        jump + vec2(0.0, -6.0),
+        jump + vec2(-6.0, 4.0),
+        jump + vec2(6.0, 4.0),
+        Color::from_hex(jump_color)
+    );
+    
+    draw_circle(pause.x, pause.y, PAUSE_RADIUS, base);
+    draw_circle_lines(pause.x, pause.y, PAUSE_RADIUS, 1.0, Color::from_hex(COLOR_SURFACE));
+    for x in [-2.0, 1.0] {
+        draw_rectangle(pause.x + x, pause.y - 3.0, 1.0, 6.0, Color::from_hex(COLOR_SURFACE));
+    }
+}
+
 fn draw_jump_velocity_bar(base_ui_rect: Rect, margin: f32, hotbar_height: f32, player: &Player) {
 
     draw_bordered_rect(
@@ -149,7 +201,19 @@ fn ease_out(x: f32, t: f32) -> f32 {
 }
 
 
+/// Draws `tile`'s sprite, if it has one, with its tint.
+pub fn draw_tile(tile: TileId, position: Vec2) {
+    let tile = tile.val();
+    if let Some(tile_index) = tile.sprite {
+        draw_from_tile_set_ex(tile_index, position, tile.tint);
+    }
+}
+
 pub fn draw_from_tile_set(tile_index: u32, position: Vec2) {
+    draw_from_tile_set_ex(tile_index, position, WHITE);
+}
+
+pub fn draw_from_tile_set_ex(tile_index: u32, position: Vec2, color: Color) {
     
     let tile_set = &*TILE_SET;
     let tileset_width = tile_set.width() / TILE_SIZE;
@@ -164,7 +228,7 @@ pub fn draw_from_tile_set(tile_index: u32, position: Vec2) {
         tile_set,
         position.x,
         position.y,
-        WHITE,
+        color,
         DrawTextureParams {
             source: Some(sprite_rect),
             ..Default::default()
@@ -0,0 +1,27 @@
+use bevy_ecs::event::Event;
+
+use crate::position::{ChunkPos, TilePos};
+use crate::tile::TileId;
+
+/// A tile in the [`crate::entity::tile_map::ChunkMap`] was set to something it wasn't before.
+#[derive(Event, Debug, Clone, Copy, PartialEq)]
+pub struct TileChanged {
+    pub pos: TilePos,
+    pub from: TileId,
+    pub to: TileId,
+}
+
+/// A chunk was generated for the first time, rather than loaded from a save.
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChunkGenerated(pub ChunkPos);
+
+/// The player walked from one chunk into another.
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChunkFocused {
+    pub from: ChunkPos,
+    pub to: ChunkPos,
+}
+
+/// The world finished saving.
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WorldSaved;
@@ -0,0 +1,271 @@
+use std::collections::BTreeMap;
+
+use bevy_ecs::system::Resource;
+use macroquad::input::{KeyCode, MouseButton};
+use serde::{Deserialize, Serialize};
+
+use super::gamepad::{AxisDirection, PadAxis, PadButton};
+use super::Action;
+
+#[cfg(not(target_family = "wasm"))]
+const BINDINGS_FILE: &str = "controls.ron";
+#[cfg(target_family = "wasm")]
+const BINDINGS_KEY: &str = "Controls";
+
+/// A physical input that can trigger an [`Action`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Binding {
+    Key(#[serde(with = "KeyCodeDef")] KeyCode),
+    Mouse(#[serde(with = "MouseButtonDef")] MouseButton),
+    Pad(PadButton),
+    /// A stick pushed past [`super::gamepad::STICK_THRESHOLD`] in one direction.
+    Stick(PadAxis, AxisDirection),
+}
+
+/// Which inputs trigger which actions. Loaded from `controls.ron` (or local storage on wasm),
+/// and written back with the defaults if there isn't one yet, so it can be edited by hand.
+#[derive(Resource, Debug, Clone, Serialize, Deserialize)]
+pub struct Bindings(pub BTreeMap<Action, Vec<Binding>>);
+
+impl Default for Bindings {
+    fn default() -> Self {
+        use Action::*;
+        use Binding::*;
+        use AxisDirection::*;
+
+        Bindings(BTreeMap::from([
+            (MoveLeft, vec![Key(KeyCode::A), Stick(PadAxis::LeftStickX, Negative), Pad(PadButton::DPadLeft)]),
+            (MoveRight, vec![Key(KeyCode::D), Stick(PadAxis::LeftStickX, Positive), Pad(PadButton::DPadRight)]),
+            (Jump, vec![Key(KeyCode::Space), Pad(PadButton::South)]),
+            (Mine, vec![Mouse(MouseButton::Left), Pad(PadButton::RightTrigger)]),
+            (Place, vec![Mouse(MouseButton::Right), Pad(PadButton::LeftTrigger)]),
+            (NextSlot, vec![Key(KeyCode::E), Pad(PadButton::RightShoulder)]),
+            (PrevSlot, vec![Key(KeyCode::Q), Pad(PadButton::LeftShoulder)]),
+            (Respawn, vec![Key(KeyCode::X), Pad(PadButton::Select)]),
+            (Pause, vec![Key(KeyCode::Escape), Pad(PadButton::Start)]),
+            (Confirm, vec![Key(KeyCode::Enter), Pad(PadButton::South)]),
+            (Quit, vec![Key(KeyCode::F10), Pad(PadButton::East)]),
+        ]))
+    }
+}
+
+impl Bindings {
+    pub fn get(&self, action: Action) -> &[Binding] {
+        self.0.get(&action).map(Vec::as_slice).unwrap_or_default()
+    }
+
+    /// Replaces the inputs bound to `action`.
+    pub fn rebind(&mut self, action: Action, bindings: Vec<Binding>) {
+        self.0.insert(action, bindings);
+    }
+
+    pub fn load() -> Bindings {
+        let Some(data) = read_config() else {
+            let bindings = Bindings::default();
+            bindings.save();
+            return bindings;
+        };
+
+        match ron::from_str::<Bindings>(&data) {
+            Ok(mut bindings) => {
+                // Actions added since the file was written keep their default keys
+                for (action, default) in Bindings::default().0 {
+                    bindings.0.entry(action).or_insert(default);
+                }
+                // Quit used to be on Q along with PrevSlot, so files written then get its own key instead
+                if bindings.get(Action::Quit) == [Binding::Key(KeyCode::Q), Binding::Pad(PadButton::East)] {
+                    bindings.rebind(Action::Quit, Bindings::default().get(Action::Quit).to_vec());
+                }
+                bindings
+            }
+            Err(e) => {
+                // Leave the broken file alone so the user can fix it
+                println!("Couldn't read controls, using defaults: {e}");
+                Bindings::default()
+            }
+        }
+    }
+
+    pub fn save(&self) {
+        let data = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .expect("Serde RON failure");
+        write_config(&data);
+    }
+}
+
+#[cfg(not(target_family = "wasm"))]
+fn read_config() -> Option<String> {
+    std::fs::read_to_string(BINDINGS_FILE).ok()
+}
+
+#[cfg(not(target_family = "wasm"))]
+fn write_config(data: &str) {
+    if let Err(e) = std::fs::write(BINDINGS_FILE, data) {
+        println!("Couldn't save controls: {e}");
+    }
+}
+
+#[cfg(target_family = "wasm")]
+fn read_config() -> Option<String> {
+    let storage = quad_storage::STORAGE.lock().expect("Storage lock fail");
+    storage.get(BINDINGS_KEY)
+}
+
+#[cfg(target_family = "wasm")]
+fn write_config(data: &str) {
+    let storage = &mut quad_storage::STORAGE.lock().expect("Storage lock fail");
+    storage.set(BINDINGS_KEY, data);
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "MouseButton")]
+enum MouseButtonDef {
+    Left,
+    Middle,
+    Right,
+    Unknown,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "KeyCode")]
+enum KeyCodeDef {
+    Space,
+    Apostrophe,
+    Comma,
+    Minus,
+    Period,
+    Slash,
+    Key0,
+    Key1,
+    Key2,
+    Key3,
+    Key4,
+    Key5,
+    Key6,
+    Key7,
+    Key8,
+    Key9,
+    Semicolon,
+    Equal,
+    A,
+    B,
+    C,
+    D,
+    E,
+    F,
+    G,
+    H,
+    I,
+    J,
+    K,
+    L,
+    M,
+    N,
+    O,
+    P,
+    Q,
+    R,
+    S,
+    T,
+    U,
+    V,
+    W,
+    X,
+    Y,
+    Z,
+    LeftBracket,
+    Backslash,
+    RightBracket,
+    GraveAccent,
+    World1,
+    World2,
+    Escape,
+    Enter,
+    Tab,
+    Backspace,
+    Insert,
+    Delete,
+    Right,
+    Left,
+    Down,
+    Up,
+    PageUp,
+    PageDown,
+    Home,
+    End,
+    CapsLock,
+    ScrollLock,
+    NumLock,
+    PrintScreen,
+    Pause,
+    F1,
+    F2,
+    F3,
+    F4,
+    F5,
+    F6,
+    F7,
+    F8,
+    F9,
+    F10,
+    F11,
+    F12,
+    F13,
+    F14,
+    F15,
+    F16,
+    F17,
+    F18,
+    F19,
+    F20,
+    F21,
+    F22,
+    F23,
+    F24,
+    F25,
+    Kp0,
+    Kp1,
+    Kp2,
+    Kp3,
+    Kp4,
+    Kp5,
+    Kp6,
+    Kp7,
+    Kp8,
+    Kp9,
+    KpDecimal,
+    KpDivide,
+    KpMultiply,
+    KpSubtract,
+    KpAdd,
+    KpEnter,
+    KpEqual,
+    LeftShift,
+    LeftControl,
+    LeftAlt,
+    LeftSuper,
+    RightShift,
+    RightControl,
+    RightAlt,
+    RightSuper,
+    Menu,
+    Back,
+    Unknown,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bindings_roundtrip() -> Result<(), Box<dyn std::error::Error>> {
+        let bindings = Bindings::default();
+
+        let data = ron::ser::to_string_pretty(&bindings, ron::ser::PrettyConfig::default())?;
+        let loaded: Bindings = ron::from_str(&data)?;
+
+        assert_eq!(bindings.0, loaded.0);
+        assert!(data.contains("MoveLeft: [\n        Key(A),\n        Stick(LeftStickX, Negative),"));
+        Ok(())
+    }
+}
@@ -0,0 +1,165 @@
+use macroquad::math::{vec2, Vec2};
+use serde::{Deserialize, Serialize};
+
+/// How far a stick has to be pushed before it counts as pressing that direction.
+pub const STICK_THRESHOLD: f32 = 0.5;
+
+/// Below this the right stick counts as centred and doesn't move the tile cursor.
+pub const AIM_DEADZONE: f32 = 0.3;
+
+/// Named by position rather than label, so bindings mean the same thing on every pad.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PadButton {
+    South,
+    East,
+    North,
+    West,
+    LeftShoulder,
+    RightShoulder,
+    LeftTrigger,
+    RightTrigger,
+    Select,
+    Start,
+    LeftThumb,
+    RightThumb,
+    DPadUp,
+    DPadDown,
+    DPadLeft,
+    DPadRight,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PadAxis {
+    LeftStickX,
+    LeftStickY,
+    RightStickX,
+    RightStickY,
+}
+
+/// Which way along a [`PadAxis`] counts as pressed. Up and right are positive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AxisDirection {
+    Positive,
+    Negative,
+}
+
+impl AxisDirection {
+    pub fn sign(self) -> f32 {
+        match self {
+            AxisDirection::Positive => 1.0,
+            AxisDirection::Negative => -1.0,
+        }
+    }
+}
+
+/// Every connected gamepad, read as one. Not `Send`, so it lives in the world as a non-send resource.
+///
+/// Browsers aren't supported, there every button reads as released.
+pub struct Gamepads {
+    #[cfg(not(target_family = "wasm"))]
+    gilrs: Option<gilrs::Gilrs>,
+}
+
+#[cfg(not(target_family = "wasm"))]
+impl Gamepads {
+    pub fn new() -> Self {
+        let gilrs = gilrs::Gilrs::new()
+            .inspect_err(|e| println!("Gamepads unavailable: {e}"))
+            .ok();
+        Gamepads { gilrs }
+    }
+
+    /// Catches up on connection and input events. Call once per frame before reading.
+    pub fn update(&mut self) {
+        if let Some(gilrs) = &mut self.gilrs {
+            while gilrs.next_event().is_some() {}
+        }
+    }
+
+    pub fn pressed(&self, button: PadButton) -> bool {
+        self.gilrs.as_ref().is_some_and(|gilrs| {
+            gilrs.gamepads().any(|(_, pad)| pad.is_pressed(button.into()))
+        })
+    }
+
+    /// The value of `axis` on whichever pad is pushing it furthest, from -1.0 to 1.0.
+    pub fn axis(&self, axis: PadAxis) -> f32 {
+        let Some(gilrs) = &self.gilrs else {
+            return 0.0;
+        };
+        gilrs.gamepads()
+            .map(|(_, pad)| pad.value(axis.into()))
+            .fold(0.0, |a: f32, b| if b.abs() > a.abs() { b } else { a })
+    }
+}
+
+#[cfg(target_family = "wasm")]
+impl Gamepads {
+    pub fn new() -> Self {
+        Gamepads {}
+    }
+
+    pub fn update(&mut self) {}
+
+    pub fn pressed(&self, _button: PadButton) -> bool {
+        false
+    }
+
+    pub fn axis(&self, _axis: PadAxis) -> f32 {
+        0.0
+    }
+}
+
+impl Gamepads {
+    /// Right stick direction in world space (y down), if it's pushed past [`AIM_DEADZONE`].
+    pub fn aim(&self) -> Option<Vec2> {
+        let stick = vec2(self.axis(PadAxis::RightStickX), -self.axis(PadAxis::RightStickY));
+        (stick.length() > AIM_DEADZONE).then(|| stick.normalize())
+    }
+}
+
+impl Default for Gamepads {
+    fn default() -> Self {
+        Gamepads::new()
+    }
+}
+
+#[cfg(not(target_family = "wasm"))]
+impl From<PadButton> for gilrs::Button {
+    fn from(button: PadButton) -> Self {
+        use gilrs::Button;
+
+        match button {
+            PadButton::South => Button::South,
+            PadButton::East => Button::East,
+            PadButton::North => Button::North,
+            PadButton::West => Button::West,
+            PadButton::LeftShoulder => Button::LeftTrigger,
+            PadButton::RightShoulder => Button::RightTrigger,
+            PadButton::LeftTrigger => Button::LeftTrigger2,
+            PadButton::RightTrigger => Button::RightTrigger2,
+            PadButton::Select => Button::Select,
+            PadButton::Start => Button::Start,
+            PadButton::LeftThumb => Button::LeftThumb,
+            PadButton::RightThumb => Button::RightThumb,
+            PadButton::DPadUp => Button::DPadUp,
+            PadButton::DPadDown => Button::DPadDown,
+            PadButton::DPadLeft => Button::DPadLeft,
+            PadButton::DPadRight => Button::DPadRight,
+        }
+    }
+}
+
+#[cfg(not(target_family = "wasm"))]
+impl From<PadAxis> for gilrs::Axis {
+    fn from(axis: PadAxis) -> Self {
+        use gilrs::Axis;
+
+        match axis {
+            PadAxis::LeftStickX => Axis::LeftStickX,
+            PadAxis::LeftStickY => Axis::LeftStickY,
+            PadAxis::RightStickX => Axis::RightStickX,
+            PadAxis::RightStickY => Axis::RightStickY,
+        }
+    }
+}
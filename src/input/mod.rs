@@ -0,0 +1,220 @@
+use bevy_ecs::prelude::*;
+use macroquad::{input::{get_char_pressed, is_key_down, is_key_pressed, is_mouse_button_down, mouse_wheel, KeyCode}, math::{ivec2, vec2, Vec2}};
+use serde::{Deserialize, Serialize};
+
+use crate::app::{windowed, App, GameSet, Plugin, ScheduleLabel_::{FixedUpdate, PreUpdate, Startup}};
+use crate::entity::camera::GameCamera;
+use crate::entity::player::PlayerTag;
+use crate::physics2::Collider;
+use crate::position::{ScreenPos, WorldPos};
+use crate::TILE_SIZE;
+
+pub use bindings::{Binding, Bindings};
+pub use gamepad::{AxisDirection, Gamepads, PadAxis, PadButton};
+
+mod bindings;
+mod gamepad;
+pub mod touch;
+
+/// How far from the player's centre the right stick puts the tile cursor.
+const PAD_AIM_REACH: f32 = TILE_SIZE * 1.5;
+
+pub struct InputPlugin;
+
+impl Plugin for InputPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .init_resource::<InputActions>()
+            .init_resource::<Bindings>()
+            .init_resource::<touch::TouchControls>()
+            .add_systems(Startup, (load_bindings, init_gamepads, touch::init_touch).run_if(windowed))
+            .configure_sets(FixedUpdate, (
+                InputTick::Begin.before(GameSet::Input),
+                InputTick::End.after(GameSet::Physics),
+            ))
+            .add_systems(PreUpdate, (
+                (poll_input, touch::touch_input).chain().run_if(windowed),
+                drop_stale_input,
+            ).chain().in_set(GameSet::Input))
+            .add_systems(FixedUpdate, end_input_tick.in_set(InputTick::End));
+    }
+}
+
+/// Input bookkeeping around each simulation tick, either side of [`GameSet::Input`] to
+/// [`GameSet::Physics`]. Anything reading [`InputActions`] in `FixedUpdate` goes between the two.
+#[derive(SystemSet, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum InputTick {
+    /// Replays and recordings hook in here, before anything reads the input.
+    Begin,
+    /// Rolls pressed actions over, so [`InputActions::just_pressed`] means "since last tick".
+    End,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Action {
+    MoveLeft,
+    MoveRight,
+    Jump,
+    Mine,
+    Place,
+    NextSlot,
+    PrevSlot,
+    Respawn,
+    Pause,
+    /// Picks the highlighted option in a menu.
+    Confirm,
+    /// Leaves the game from the main menu, or the world from the pause menu.
+    Quit,
+}
+
+/// A set of [`Action`]s, one bit each.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct ActionSet(u16);
+
+impl ActionSet {
+    pub fn contains(self, action: Action) -> bool {
+        self.0 & (1 << action as u16) != 0
+    }
+    pub fn set(&mut self, action: Action, on: bool) {
+        if on {
+            self.0 |= 1 << action as u16;
+        } else {
+            self.0 &= !(1 << action as u16);
+        }
+    }
+}
+
+/// What the player is asking for, in terms of [`Action`]s rather than keys.
+///
+/// Simulation systems read this on each tick instead of polling macroquad, so a headless
+/// [`App`] or a replay can drive them by writing to it directly.
+#[derive(Resource, Default, Debug, Clone)]
+pub struct InputActions {
+    held: ActionSet,
+    previous: ActionSet,
+    /// Held since before they'd mean something else, so they're ignored until they're let go.
+    stale: ActionSet,
+    /// Mouse wheel movement since the last tick. It's an axis rather than an action, so it isn't rebindable.
+    pub scroll: f32,
+    /// Text typed since the last tick, for naming things in menus. Backspace comes through as `'\u{8}'`.
+    pub typed: String,
+    /// Where the mouse is on screen, for drawing.
+    pub cursor: Option<ScreenPos>,
+    /// The point in the world the player is aiming at. Ticks use this rather than
+    /// `cursor`, so they don't depend on where the camera happened to be.
+    pub aim: Option<WorldPos>,
+}
+
+impl InputActions {
+    pub fn pressed(&self, action: Action) -> bool {
+        self.held.contains(action)
+    }
+    /// Pressed this tick but not the last one.
+    pub fn just_pressed(&self, action: Action) -> bool {
+        self.held.contains(action) && !self.previous.contains(action)
+    }
+    pub fn held(&self) -> ActionSet {
+        self.held
+    }
+    pub fn set(&mut self, action: Action, pressed: bool) {
+        self.held.set(action, pressed);
+    }
+    pub fn set_held(&mut self, held: ActionSet) {
+        self.held = held;
+    }
+    /// Ignores `action` until it's let go, if it's held, so it has to be pressed again.
+    pub fn ignore_until_released(&mut self, action: Action) {
+        self.stale.set(action, self.held.contains(action));
+        self.held.set(action, false);
+    }
+}
+
+/// Lets go of whatever's still held from [`InputActions::ignore_until_released`], and forgets what's been let go of.
+fn drop_stale_input(mut input: ResMut<InputActions>) {
+    let input = input.as_mut();
+    input.stale = ActionSet(input.stale.0 & input.held.0);
+    input.held = ActionSet(input.held.0 & !input.stale.0);
+}
+
+fn load_bindings(mut bindings: ResMut<Bindings>) {
+    *bindings = Bindings::load();
+}
+
+fn init_gamepads(world: &mut World) {
+    world.insert_non_send_resource(Gamepads::new());
+}
+
+fn poll_input(
+    mut input: ResMut<InputActions>,
+    bindings: Res<Bindings>,
+    mut gamepads: NonSendMut<Gamepads>,
+    camera: Query<&GameCamera>,
+    player: Query<&Collider, With<PlayerTag>>,
+    mut last_mouse: Local<Option<ScreenPos>>,
+    mut pad_aim: Local<Option<Vec2>>,
+) {
+    gamepads.update();
+    
+    let mut held = ActionSet::default();
+    for (&action, action_bindings) in &bindings.0 {
+        let down = action_bindings.iter().any(|binding| match *binding {
+            Binding::Key(key) => is_key_down(key),
+            Binding::Mouse(button) => is_mouse_button_down(button),
+            Binding::Pad(button) => gamepads.pressed(button),
+            Binding::Stick(axis, direction) => gamepads.axis(axis) * direction.sign() > gamepad::STICK_THRESHOLD,
+        });
+        held.set(action, down);
+    }
+
+    input.held = held;
+    // Frames can go by without a tick, so scrolling piles up until one consumes it
+    input.scroll += get_scroll_stepped().y;
+    while let Some(c) = get_char_pressed() {
+        if !c.is_control() {
+            input.typed.push(c);
+        }
+    }
+    if is_key_pressed(KeyCode::Backspace) {
+        input.typed.push('\u{8}');
+    }
+    
+    // Whichever of the mouse and the right stick moved last gets the cursor. The stick
+    // aim sticks around when it's let go, so it follows the player until the mouse moves.
+    let mouse = ScreenPos::mouse();
+    if mouse != *last_mouse {
+        *last_mouse = mouse;
+        *pad_aim = None;
+    }
+    if let Some(direction) = gamepads.aim() {
+        *pad_aim = Some(direction);
+    }
+    
+    let Ok(camera) = camera.get_single() else {
+        return;
+    };
+    match (*pad_aim, player.get_single()) {
+        (Some(direction), Ok(collider)) => {
+            let centre = collider.pos + ivec2(collider.width, collider.height).as_vec2() / 2.;
+            let aim = WorldPos(centre + direction * PAD_AIM_REACH);
+            input.aim = Some(aim);
+            input.cursor = Some(aim.to_screen(&camera.0));
+        }
+        _ => {
+            input.cursor = mouse;
+            input.aim = mouse.map(|pos| pos.to_world(&camera.0));
+        }
+    }
+}
+
+fn end_input_tick(mut input: ResMut<InputActions>) {
+    input.previous = input.held;
+    input.scroll = 0.0;
+    input.typed.clear();
+}
+
+pub fn get_scroll_stepped() -> Vec2 {
+    let scr = mouse_wheel();
+    let scr = vec2(scr.0, scr.1);
+
+    scr
+}
@@ -0,0 +1,162 @@
+use bevy_ecs::prelude::*;
+use macroquad::input::{simulate_mouse_with_touch, touches, TouchPhase};
+use macroquad::math::{vec2, Vec2};
+
+use crate::entity::camera::GameCamera;
+use crate::entity::ui::hotbar_rect;
+use crate::position::ScreenPos;
+use crate::time::Time;
+use crate::{VIRTUAL_HEIGHT, VIRTUAL_WIDTH};
+
+use super::{Action, InputActions};
+
+pub const JOYSTICK_CENTRE: Vec2 = vec2(30.0, VIRTUAL_HEIGHT - 72.0);
+pub const JOYSTICK_RADIUS: f32 = 20.0;
+pub const JUMP_CENTRE: Vec2 = vec2(VIRTUAL_WIDTH - 30.0, VIRTUAL_HEIGHT - 72.0);
+pub const JUMP_RADIUS: f32 = 16.0;
+pub const PAUSE_CENTRE: Vec2 = vec2(VIRTUAL_WIDTH - 12.0, 12.0);
+pub const PAUSE_RADIUS: f32 = 8.0;
+
+/// How far the joystick has to be pushed, as a fraction of its radius, to start walking.
+const JOYSTICK_THRESHOLD: f32 = 0.3;
+/// Touches on the world held longer than this place a tile instead of mining one.
+const LONG_PRESS_TIME: f32 = 0.35;
+
+/// On-screen controls for phones: a joystick and jump button, tap to mine, long-press to place.
+#[derive(Resource, Default, Debug)]
+pub struct TouchControls {
+    /// Set once we've seen a touch, so mouse players don't get controls drawn over the game.
+    pub active: bool,
+    joystick: Option<(u64, Vec2)>,
+    jump: Option<u64>,
+    world: Option<WorldTouch>,
+    /// A tap, held until a tick has seen it: taps can start and end between ticks.
+    tap: Option<Tap>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct WorldTouch {
+    id: u64,
+    position: ScreenPos,
+    held_for: f32,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Tap {
+    action: Action,
+    position: Option<ScreenPos>,
+    tick: u64,
+}
+
+impl TouchControls {
+    /// Where the joystick knob is, relative to [`JOYSTICK_CENTRE`].
+    pub fn knob(&self) -> Vec2 {
+        self.joystick.map(|(_, knob)| knob).unwrap_or_default()
+    }
+
+    pub fn jump_held(&self) -> bool {
+        self.jump.is_some()
+    }
+}
+
+pub(super) fn init_touch() {
+    // Otherwise every touch is also a left click, and mines whatever is under the joystick
+    simulate_mouse_with_touch(false);
+}
+
+pub(super) fn touch_input(
+    mut touch: ResMut<TouchControls>,
+    mut input: ResMut<InputActions>,
+    time: Res<Time>,
+    camera: Query<&GameCamera>,
+) {
+    let touch = touch.as_mut();
+
+    for finger in touches() {
+        touch.active = true;
+        // Fingers can wander off into the letterbox, they still need letting go of
+        let position = ScreenPos::from_real(finger.position);
+
+        match (finger.phase, position) {
+            (TouchPhase::Started, Some(position)) => {
+                if position.0.distance(JOYSTICK_CENTRE) < JOYSTICK_RADIUS * 1.5 {
+                    touch.joystick = Some((finger.id, Vec2::ZERO));
+                } else if position.0.distance(JUMP_CENTRE) < JUMP_RADIUS * 1.5 {
+                    touch.jump = Some(finger.id);
+                } else if position.0.distance(PAUSE_CENTRE) < PAUSE_RADIUS * 1.5 {
+                    touch.tap = Some(Tap { action: Action::Pause, position: None, tick: time.ticks() });
+                } else if hotbar_rect().contains(position.0) {
+                    touch.tap = Some(Tap { action: Action::NextSlot, position: None, tick: time.ticks() });
+                } else {
+                    touch.world = Some(WorldTouch { id: finger.id, position, held_for: 0.0 });
+                }
+            }
+            (TouchPhase::Started, None) => {}
+            (TouchPhase::Moved | TouchPhase::Stationary, _) => {
+                let Some(position) = position else {
+                    continue;
+                };
+                if let Some((id, knob)) = &mut touch.joystick {
+                    if *id == finger.id {
+                        *knob = (position.0 - JOYSTICK_CENTRE).clamp_length_max(JOYSTICK_RADIUS);
+                    }
+                }
+                if let Some(world) = &mut touch.world {
+                    if world.id == finger.id {
+                        world.position = position;
+                    }
+                }
+            }
+            (TouchPhase::Ended | TouchPhase::Cancelled, _) => {
+                if touch.joystick.is_some_and(|(id, _)| id == finger.id) {
+                    touch.joystick = None;
+                }
+                if touch.jump == Some(finger.id) {
+                    touch.jump = None;
+                }
+                if let Some(world) = touch.world.filter(|world| world.id == finger.id) {
+                    if world.held_for < LONG_PRESS_TIME && finger.phase == TouchPhase::Ended && position.is_some() {
+                        touch.tap = Some(Tap { action: Action::Mine, position, tick: time.ticks() });
+                    }
+                    touch.world = None;
+                }
+            }
+        }
+    }
+
+    if !touch.active {
+        return;
+    }
+
+    let knob = touch.knob() / JOYSTICK_RADIUS;
+    if knob.x < -JOYSTICK_THRESHOLD {
+        input.set(Action::MoveLeft, true);
+    }
+    if knob.x > JOYSTICK_THRESHOLD {
+        input.set(Action::MoveRight, true);
+    }
+    if touch.jump_held() {
+        input.set(Action::Jump, true);
+    }
+
+    let mut aim_at = None;
+    if let Some(world) = &mut touch.world {
+        world.held_for += time.delta();
+        if world.held_for >= LONG_PRESS_TIME {
+            input.set(Action::Place, true);
+            aim_at = Some(world.position);
+        }
+    }
+    match touch.tap {
+        Some(tap) if tap.tick == time.ticks() => {
+            input.set(tap.action, true);
+            aim_at = tap.position.or(aim_at);
+        }
+        _ => touch.tap = None,
+    }
+
+    if let (Some(position), Ok(camera)) = (aim_at, camera.get_single()) {
+        input.cursor = Some(position);
+        input.aim = Some(position.to_world(&camera.0));
+    }
+}
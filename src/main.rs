@@ -1,28 +1,43 @@
 use std::io::BufWriter;
 use std::sync::LazyLock;
 use app::App;
-use app::ScheduleLabel_::Startup;
+use app::ScheduleLabel_::OnEnter;
 use asefile::AsepriteFile;
-use bevy_ecs::system::Commands;
-use entity::player::new_player;
-use entity::player::PlayerTag;
-use entity::tile_map::ChunkMap;
+use bevy_ecs::world::{Mut, World};
+use entity::spawn_world;
+use entity::player::SavedPlayer;
+use entity::tile_map::{entities_key, player_key, set_aside_record, ChunkMap};
 use entity::EntityPlugin;
+use input::InputPlugin;
+use replay::{Playback, Recorder, Replay, ReplayPlugin};
+use save::slots::{self, CurrentSlot};
+use save::scene::SavedScene;
+use save::{LoadFailed, SaveError, Storage};
+use state::{GameState, State};
 use image::codecs::png::PngEncoder;
 use macroquad::prelude::*;
 
 pub mod entity;
+pub mod events;
 pub mod grid;
+pub mod input;
 pub mod physics2;
 pub mod position;
+pub mod replay;
+pub mod save;
+pub mod state;
 pub mod tile;
 pub mod app;
+pub mod time;
+pub mod worldgen;
 
 pub const VIRTUAL_WIDTH: f32 = 256.0;
 pub const VIRTUAL_HEIGHT: f32 = 224.0;
 pub const TILE_SIZE: f32 = 16.0;
 pub const SMOOTH_CAMERA: bool = false;
 pub const SAVE_TIMER: f32 = 10.0;
+/// Saves early after this many tiles are changed, `None` to only save on [`SAVE_TIMER`].
+pub const SAVE_EDITS: Option<u32> = Some(64);
 
 static TILE_SET: LazyLock<Texture2D> = LazyLock::new(|| {
     let ase = AsepriteFile::read(&include_bytes!("../assets/tileset.ase")[..]).unwrap();
@@ -59,9 +74,14 @@ async fn main() {
     let mut app = App::new();
     
     app
-        .add_systems(Startup, init_entities)
+        .insert_resource(Storage::platform())
+        .add_systems(OnEnter(GameState::Loading), init_entities)
         // .add_plugin(physics2::PhysicsPlugin)
-        .add_plugin(EntityPlugin);
+        .add_plugin(InputPlugin)
+        .add_plugin(EntityPlugin)
+        .add_plugin(ReplayPlugin);
+    
+    replay_args(&mut app);
 
     app.run().await;
     
@@ -154,12 +174,82 @@ async fn main() {
 //     }
 // }
 
-fn init_entities(mut commands: Commands) {
-    let mut chunk_map = ChunkMap::load().unwrap_or(ChunkMap::default());
-    let (player , collider, actor) = new_player(&mut chunk_map);
-    dbg!(&player);
-    commands.spawn(chunk_map);
-    commands.spawn((PlayerTag, player, collider, actor));
+fn init_entities(world: &mut World) {
+    if world.contains_resource::<Playback>() {
+        world.resource_scope(|world, playback: Mut<Playback>| playback.start(world));
+    } else if let Err(error) = load_world(world) {
+        // Starting a new world would save over it, so ask first
+        println!("Couldn't load: {error}");
+        let storage = world.resource::<Storage>().clone();
+        let slot = world.resource::<CurrentSlot>().0.clone();
+        world.insert_resource(LoadFailed { error, has_backup: ChunkMap::has_backup(&storage, &slot) });
+        world.resource_mut::<State>().set(GameState::LoadFailed);
+        return;
+    }
+    
+    // Loading doesn't take long enough to draw anything yet
+    world.resource_mut::<State>().set(GameState::Playing);
+}
+
+/// Spawns the world in the current slot as it was saved, or generates it if it's never been played.
+fn load_world(world: &mut World) -> Result<(), SaveError> {
+    let storage = world.resource::<Storage>().clone();
+    let slot = world.resource::<CurrentSlot>().0.clone();
+    let chunk_map = match ChunkMap::load(&storage, &slot) {
+        Ok(map) => map,
+        Err(SaveError::Missing) => {
+            // Never played yet, so generate it from the seed it was made with
+            let seed = slots::get(&storage, &slot).ok().and_then(|slot| slot.seed);
+            ChunkMap::new(seed.unwrap_or_else(worldgen::random_seed)).saved_in(&storage, &slot)
+        }
+        Err(error) => return Err(error),
+    };
+    let player = loaded("the player", &storage, &player_key(&slot), SavedPlayer::load(&storage, &slot));
+    let scene = loaded("the world's entities", &storage, &entities_key(&slot), SavedScene::load(&storage, &slot));
+
+    spawn_world(world, chunk_map, player);
+    if let Some(Err(e)) = scene.map(|scene| scene.spawn(world)) {
+        println!("Couldn't spawn the world's entities: {e}");
+    }
+    Ok(())
+}
+
+/// Whatever was saved alongside the map under `key`, if anything. Losing it isn't worth not playing over,
+/// but it's kept out of the way of the next save in case it can be read some day.
+fn loaded<T>(what: &str, storage: &Storage, key: &str, result: Result<T, SaveError>) -> Option<T> {
+    match result {
+        Ok(value) => Some(value),
+        Err(SaveError::Missing) => None,
+        Err(e) => {
+            println!("Couldn't load {what}, starting without it: {e}");
+            if let Err(e) = set_aside_record(storage, key) {
+                println!("Couldn't move {what} out of the way, the next save replaces it: {e}");
+            }
+            None
+        }
+    }
+}
+
+/// `--record <file>` writes this session to a replay, `--replay <file>` plays one back.
+fn replay_args(app: &mut App) {
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match (arg.as_str(), args.next()) {
+            ("--record", Some(path)) => {
+                app.insert_resource(Recorder::new(path));
+            }
+            ("--replay", Some(path)) => match Replay::load(path.as_ref()) {
+                Ok(replay) => {
+                    // Straight into the replay, the menu would only be in the way
+                    app
+                        .insert_resource(Playback::new(replay))
+                        .insert_resource(State::new(GameState::Loading));
+                }
+                Err(e) => println!("Couldn't load replay {path}: {e}"),
+            },
+            _ => println!("Ignoring argument {arg}"),
+        }
+    }
 }
 
 #[test]
@@ -28,6 +28,7 @@ use macroquad::math::Rect;
 use serde::{Deserialize, Serialize};
 use std::collections::HashSet;
 use crate::entity::tile_map::ChunkMap;
+use crate::save::{SaveError, Storage};
 
 
 
@@ -80,18 +81,20 @@ impl World {
         }
     }
     
-    pub fn save(&self) {
+    pub fn save(&self) -> Result<(), SaveError> {
         println!("Save");
         let data = bincode::serialize(&self).expect("Serde Bincode failure");
         let data = BASE64_STANDARD.encode(data);
-        let storage = &mut quad_storage::STORAGE.lock().expect("Storage lock fail");
-        storage.set("World", &data);
-        storage.set("foo", &BASE64_STANDARD.encode(b"bar"));
+        let storage = Storage::platform();
+        let mut storage = storage.lock()?;
+        storage.set("World", &data)?;
+        storage.set("foo", &BASE64_STANDARD.encode(b"bar"))
     }
     
     pub fn load() -> Option<World> {
         println!("Load");
-        let storage = &mut quad_storage::STORAGE.lock().expect("Storage lock fail");
+        let storage = Storage::platform();
+        let storage = storage.lock().ok()?;
        
         // if storage.get("foo").is_none() {
         //     return None;
@@ -1,4 +1,5 @@
 use std::collections::HashSet;
+use std::hash::{Hash, Hasher};
 use macroquad::math::{ivec2, vec2, Rect, Vec2};
 use bevy_ecs::component::Component;
 use serde::{Deserialize, Serialize};
@@ -45,6 +46,7 @@ pub struct Collider {
     collidable: bool,
     squished: bool,
     pub pos: Vec2,
+    prev_pos: Vec2,
     pub width: i32,
     pub height: i32,
     x_remainder: f32,
@@ -54,6 +56,35 @@ pub struct Collider {
     seen_wood: bool,
 }
 
+impl Collider {
+    /// Remembers where the collider was before this tick's movement.
+    pub fn begin_tick(&mut self) {
+        self.prev_pos = self.pos;
+    }
+
+    /// Position between the previous and current tick, snapped to whole pixels.
+    pub fn interpolated_pos(&self, alpha: f32) -> Vec2 {
+        self.prev_pos.lerp(self.pos, alpha).round()
+    }
+
+    /// Feeds everything that affects future movement into `state`, for comparing simulations.
+    pub fn hash_state(&self, state: &mut impl Hasher) {
+        self.pos.x.to_bits().hash(state);
+        self.pos.y.to_bits().hash(state);
+        self.x_remainder.to_bits().hash(state);
+        self.y_remainder.to_bits().hash(state);
+        (self.width, self.height, self.descent, self.seen_wood).hash(state);
+    }
+
+    /// Moves the collider without sweeping or interpolating from the old position.
+    pub fn teleport(&mut self, pos: Vec2) {
+        self.pos = pos;
+        self.prev_pos = pos;
+        self.x_remainder = 0.;
+        self.y_remainder = 0.;
+    }
+}
+
 pub fn add_actor(pos: Vec2, width: i32, height: i32, map: &mut ChunkMap) -> (Actor, Collider) {
     let actor = Actor;
 
@@ -73,6 +104,7 @@ pub fn add_actor(pos: Vec2, width: i32, height: i32, map: &mut ChunkMap) -> (Act
             collidable: true,
             squished: false,
             pos,
+            prev_pos: pos,
             width,
             height,
             x_remainder: 0.,
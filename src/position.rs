@@ -23,13 +23,15 @@ impl ScreenPos {
     }
     
     pub fn mouse() -> Option<ScreenPos> {
-        let screen = Rect::new(0., 0., VIRTUAL_WIDTH, VIRTUAL_HEIGHT);
-    
         let mouse_pos= mouse_position();
-        let mouse_pos = vec2(mouse_pos.0, mouse_pos.1);
-        let mouse_pos = real_to_virtual_screen_space(mouse_pos);
+        ScreenPos::from_real(vec2(mouse_pos.0, mouse_pos.1))
+    }
+    /// Converts a position in window pixels, `None` if it's in the letterbox.
+    pub fn from_real(pos: Vec2) -> Option<ScreenPos> {
+        let screen = Rect::new(0., 0., VIRTUAL_WIDTH, VIRTUAL_HEIGHT);
+        let pos = real_to_virtual_screen_space(pos);
     
-        screen.contains(mouse_pos).then_some(ScreenPos(mouse_pos))
+        screen.contains(pos).then_some(ScreenPos(pos))
     }
     pub fn to_world(self, camera: &Camera2D) -> WorldPos {
         WorldPos(
@@ -41,6 +43,11 @@ impl ScreenPos {
     }
 }
 impl WorldPos {
+    pub fn to_screen(self, camera: &Camera2D) -> ScreenPos {
+        ScreenPos(
+            self.0 - camera.target + Rect::new(0., 0., VIRTUAL_WIDTH, VIRTUAL_HEIGHT).size()/2.
+        )
+    }
     pub fn snap(self) -> WorldPos {
         TilePos(self.to_tile().0.floor()).to_world()
     }
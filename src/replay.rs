@@ -0,0 +1,453 @@
+use std::error::Error;
+use std::fmt;
+use std::hash::Hasher;
+use std::path::{Path, PathBuf};
+
+use bevy_ecs::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::app::{App, Plugin, ScheduleLabel_::{FixedUpdate, OnEnter}};
+use crate::entity::player::{PlayerTag, SavedPlayer};
+use crate::entity::tile_map::{ChunkMap, SavedRegions};
+use crate::entity::{despawn_world, saved_player, spawn_world, EntityPlugin};
+use crate::input::{Action, ActionSet, InputActions, InputPlugin, InputTick};
+use crate::physics2::Collider;
+use crate::position::WorldPos;
+use crate::save::scene::SavedScene;
+use crate::state::{in_state, GameState, State};
+use crate::time::FIXED_TIMESTEP;
+
+/// A recording is written out this often, in ticks, so closing the game loses at most a second.
+const FLUSH_TICKS: usize = 60;
+
+pub struct ReplayPlugin;
+
+impl Plugin for ReplayPlugin {
+    fn build(&self, app: &mut App) {
+        // Only ticks that move the world are recorded, paused ones would just be padding
+        app
+            .add_systems(FixedUpdate, (
+                record_tick.run_if(resource_exists::<Recorder>),
+                play_tick.run_if(resource_exists::<Playback>),
+            ).run_if(in_state(GameState::Playing)).in_set(InputTick::Begin))
+            .add_systems(FixedUpdate, (
+                flush_recording.run_if(resource_exists::<Recorder>),
+                check_playback.run_if(resource_exists::<Playback>),
+            ).run_if(in_state(GameState::Playing)).after(InputTick::End))
+            .add_systems(OnEnter(GameState::MainMenu), stop_replay.after(despawn_world));
+    }
+}
+
+/// Everything the simulation reads from [`InputActions`] during one tick.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct TickInput {
+    pub held: ActionSet,
+    pub scroll: f32,
+    pub aim: Option<WorldPos>,
+}
+
+impl From<&InputActions> for TickInput {
+    fn from(input: &InputActions) -> Self {
+        TickInput {
+            held: input.held(),
+            scroll: input.scroll,
+            aim: input.aim,
+        }
+    }
+}
+
+/// A world as it was when recording started, and the input for every tick after that.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Replay {
+    pub start: ChunkMap,
+    /// The regions `start` read chunks back from while recording. Only those, the rest of the save isn't needed.
+    pub saved: SavedRegions,
+    /// Where the player was and what they had. `None` if there wasn't one.
+    pub player: Option<SavedPlayer>,
+    /// Every [`crate::save::scene::Saveable`] entity there was.
+    pub scene: SavedScene,
+    pub ticks: Vec<TickInput>,
+    /// [`state_hash`] after the last tick.
+    pub final_hash: u64,
+}
+
+impl Replay {
+    pub fn load(path: &Path) -> Result<Replay, Box<dyn Error>> {
+        let data = std::fs::read(path)?;
+        Ok(bincode::deserialize(&data[..])?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let data = bincode::serialize(self)?;
+        std::fs::write(path, data)?;
+        Ok(())
+    }
+
+    /// The world as it is now, to record from. The map keeps a copy of every region it reads from here on.
+    fn record_start(world: &mut World) -> Replay {
+        let player = saved_player(world);
+        let scene = SavedScene::from_world(world).unwrap_or_else(|e| {
+            println!("Couldn't record the world's entities, the replay starts without them: {e}");
+            SavedScene::default()
+        });
+        let mut map = world.query::<&mut ChunkMap>().single_mut(world);
+        let start = map.clone();
+        map.copy_region_reads();
+        Replay { start, saved: SavedRegions::default(), player, scene, ticks: vec![], final_hash: 0 }
+    }
+
+    /// Spawns the world to play the replay back in, with its own copy of the regions it read.
+    pub fn spawn(&self, world: &mut World) {
+        spawn_world(world, self.start.clone().with_saved_regions(&self.saved), self.player.clone());
+        if let Err(e) = self.scene.spawn(world) {
+            println!("Couldn't spawn the replay's entities: {e}");
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Diverged {
+    pub expected: u64,
+    pub actual: u64,
+}
+
+impl fmt::Display for Diverged {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "replay diverged: expected state {:016x}, got {:016x}", self.expected, self.actual)
+    }
+}
+
+impl Error for Diverged {}
+
+/// Records every tick's input, writing it to `path` as it goes (if there is one).
+#[derive(Resource, Default)]
+pub struct Recorder {
+    path: Option<PathBuf>,
+    replay: Option<Replay>,
+}
+
+impl Recorder {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Recorder {
+            path: Some(path.into()),
+            replay: None,
+        }
+    }
+
+    pub fn replay(&self) -> Option<&Replay> {
+        self.replay.as_ref()
+    }
+}
+
+/// Feeds a [`Replay`]'s input into the simulation in place of the player's.
+#[derive(Resource)]
+pub struct Playback {
+    replay: Replay,
+    tick: usize,
+    finished: bool,
+}
+
+impl Playback {
+    pub fn new(replay: Replay) -> Self {
+        Playback {
+            replay,
+            tick: 0,
+            finished: false,
+        }
+    }
+
+    /// Spawns the world the replay starts in.
+    pub fn start(&self, world: &mut World) {
+        self.replay.spawn(world);
+    }
+}
+
+/// Run condition for while a replay is being recorded or played back.
+pub fn in_replay(recorder: Option<Res<Recorder>>, playback: Option<Res<Playback>>) -> bool {
+    recorder.is_some() || playback.is_some()
+}
+
+/// A hash of the world and the player, stable across runs and platforms.
+pub fn state_hash(map: &ChunkMap, player: &Collider) -> u64 {
+    let mut state = Fnv1a::default();
+    map.hash_state(&mut state);
+    player.hash_state(&mut state);
+    state.finish()
+}
+
+/// Plays `replay` back in a headless [`App`] and checks it ends up where the recording did.
+pub fn verify(replay: &Replay) -> Result<(), Diverged> {
+    let mut app = App::headless();
+    app
+        .add_plugin(InputPlugin)
+        .add_plugin(EntityPlugin)
+        .add_plugin(ReplayPlugin)
+        .insert_resource(Playback::new(replay.clone()))
+        .insert_resource(State::new(GameState::Playing));
+    replay.spawn(&mut app.world);
+
+    app.startup();
+    // One tick per frame, the frame length doesn't matter otherwise
+    app.step(replay.ticks.len() as u32, FIXED_TIMESTEP);
+
+    let actual = world_hash(&mut app.world);
+    if actual == replay.final_hash {
+        Ok(())
+    } else {
+        Err(Diverged { expected: replay.final_hash, actual })
+    }
+}
+
+pub(crate) fn world_hash(world: &mut World) -> u64 {
+    let mut maps = world.query::<&ChunkMap>();
+    let mut players = world.query_filtered::<&Collider, With<PlayerTag>>();
+    state_hash(maps.single(world), players.single(world))
+}
+
+fn record_tick(world: &mut World) {
+    if world.resource::<Recorder>().replay.is_none() {
+        let replay = Replay::record_start(world);
+        world.resource_mut::<Recorder>().replay = Some(replay);
+    }
+    let tick = TickInput::from(world.resource::<InputActions>());
+    if let Some(replay) = world.resource_mut::<Recorder>().replay.as_mut() {
+        replay.ticks.push(tick);
+    }
+}
+
+fn flush_recording(mut recorder: ResMut<Recorder>, mut map: Query<&mut ChunkMap>, player: Query<&Collider, With<PlayerTag>>) {
+    let recorder = recorder.as_mut();
+    let Some(replay) = recorder.replay.as_mut() else {
+        return;
+    };
+
+    let mut map = map.single_mut();
+    replay.saved.extend(map.take_region_reads());
+    replay.final_hash = state_hash(&map, player.single());
+
+    if let Some(path) = &recorder.path {
+        if replay.ticks.len() % FLUSH_TICKS == 0 {
+            if let Err(e) = replay.save(path) {
+                println!("Couldn't write replay to {}: {e}", path.display());
+            }
+        }
+    }
+}
+
+fn play_tick(mut playback: ResMut<Playback>, mut input: ResMut<InputActions>) {
+    let playback = playback.as_mut();
+    let Some(tick) = playback.replay.ticks.get(playback.tick) else {
+        return;
+    };
+
+    // Pausing is up to whoever is watching, not whoever recorded it
+    let mut held = tick.held;
+    for action in [Action::Pause, Action::Confirm, Action::Quit] {
+        held.set(action, input.pressed(action));
+    }
+    input.set_held(held);
+    input.scroll = tick.scroll;
+    input.aim = tick.aim;
+    playback.tick += 1;
+}
+
+fn check_playback(mut playback: ResMut<Playback>, map: Query<&ChunkMap>, player: Query<&Collider, With<PlayerTag>>) {
+    if playback.finished || playback.tick < playback.replay.ticks.len() {
+        return;
+    }
+    playback.finished = true;
+
+    let actual = state_hash(map.single(), player.single());
+    if actual == playback.replay.final_hash {
+        println!("Replay finished, state matches ({actual:016x})");
+    } else {
+        println!("{}", Diverged { expected: playback.replay.final_hash, actual });
+    }
+}
+
+/// A replay covers one visit to one world, so it ends when the player leaves it.
+fn stop_replay(mut commands: Commands, recorder: Option<Res<Recorder>>) {
+    if let Some(Recorder { path: Some(path), replay: Some(replay) }) = recorder.as_deref() {
+        if let Err(e) = replay.save(path) {
+            println!("Couldn't write replay to {}: {e}", path.display());
+        }
+    }
+    commands.remove_resource::<Recorder>();
+    commands.remove_resource::<Playback>();
+}
+
+/// FNV-1a, because `DefaultHasher` makes no promises about staying the same between
+/// Rust versions and replay files need to outlive those.
+struct Fnv1a(u64);
+
+impl Default for Fnv1a {
+    fn default() -> Self {
+        Fnv1a(0xcbf29ce484222325)
+    }
+}
+
+impl Hasher for Fnv1a {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= *byte as u64;
+            self.0 = self.0.wrapping_mul(0x100000001b3);
+        }
+    }
+
+    // Lengths are hashed as usize, which would make wasm32 disagree with everything else
+    fn write_usize(&mut self, i: usize) {
+        self.write(&(i as u64).to_le_bytes());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use macroquad::math::{ivec2, uvec2, vec2, Vec2};
+    use crate::entity::player::Facing;
+    use crate::entity::tile_map::ChunkUnloading;
+    use crate::position::ChunkPos;
+    use crate::save::{SaveError, Storage};
+    use crate::tile::TileId;
+    use super::*;
+
+    fn record(script: impl Fn(u32, &mut InputActions), ticks: u32) -> Replay {
+        record_with(ChunkMap::default(), None, |_| {}, script, ticks)
+    }
+
+    /// Records `script` in `map` with `player` in it, in an app `setup` has had a go at first, then leaves the world.
+    fn record_with(
+        map: ChunkMap,
+        player: Option<SavedPlayer>,
+        setup: impl FnOnce(&mut App),
+        script: impl Fn(u32, &mut InputActions),
+        ticks: u32,
+    ) -> Replay {
+        let mut app = App::headless();
+        setup(&mut app);
+        app
+            .add_plugin(InputPlugin)
+            .add_plugin(EntityPlugin)
+            .add_plugin(ReplayPlugin)
+            .init_resource::<Recorder>()
+            .insert_resource(State::new(GameState::Playing));
+        spawn_world(&mut app.world, map, player);
+        app.startup();
+
+        for tick in 0..ticks {
+            script(tick, &mut app.world.resource_mut::<InputActions>());
+            app.step(1, FIXED_TIMESTEP);
+        }
+        let replay = app.world.resource::<Recorder>().replay().unwrap().clone();
+        despawn_world(&mut app.world);
+        replay
+    }
+
+    fn jetpack_and_dig(tick: u32, input: &mut InputActions) {
+        input.set(Action::MoveRight, tick < 40);
+        // Jump, let go, then press again and hold to jetpack
+        input.set(Action::Jump, (10..14).contains(&tick) || (20..60).contains(&tick));
+        input.set(Action::NextSlot, tick == 90);
+        input.aim = Some(WorldPos(vec2(320., 136.)));
+        input.set(Action::Mine, (100..110).contains(&tick));
+        input.set(Action::Place, (120..130).contains(&tick));
+    }
+
+    fn fly_right(_: u32, input: &mut InputActions) {
+        input.set(Action::MoveRight, true);
+        input.set(Action::Jump, true);
+    }
+
+    #[test]
+    fn recording_replays_to_same_state() {
+        let replay = record(jetpack_and_dig, 180);
+
+        assert_eq!(replay.ticks.len(), 180);
+        assert_eq!(verify(&replay), Ok(()));
+    }
+
+    #[test]
+    fn recording_keeps_chunks_playback_needs() {
+        // Flying right goes through far more chunks than this
+        let replay = record_with(
+            ChunkMap::default(),
+            None,
+            |app| { app.insert_resource(ChunkUnloading { radius: 0, max_loaded: 1 }); },
+            fly_right,
+            300,
+        );
+        assert_eq!(verify(&replay), Ok(()));
+    }
+
+    #[test]
+    fn replays_bring_what_was_saved() -> Result<(), SaveError> {
+        // A change that's only in the save by the time recording starts, somewhere that isn't loaded until later
+        let storage = Storage::memory();
+        let mut map = ChunkMap::default().saved_in(&storage, "World/0");
+        map.get_mut(ChunkPos(ivec2(3, 0))).set(uvec2(3, 4), TileId::GoldOre);
+        // And one nowhere near, which the replay has no need of
+        map.get_mut(ChunkPos(ivec2(-40, 0))).set(uvec2(3, 4), TileId::GoldOre);
+        map.save()?;
+
+        let replay = record_with(ChunkMap::load(&storage, "World/0")?, None, |_| {}, fly_right, 600);
+        assert_eq!(replay.saved.positions().collect::<Vec<_>>(), [ivec2(0, 0)]);
+        // Played back from a file, nowhere near the save
+        let replay: Replay = bincode::deserialize(&bincode::serialize(&replay).unwrap()).unwrap();
+        assert_eq!(verify(&replay), Ok(()));
+        Ok(())
+    }
+
+    #[test]
+    fn replays_start_where_the_player_was() {
+        // Off to the side of the spawn point, holding gold
+        let player = SavedPlayer {
+            pos: vec2(40., 0.),
+            speed: Vec2::ZERO,
+            facing: Facing::Left,
+            selected_item: 0,
+            inventory: [TileId::GoldOre; 4],
+            camera_target: Vec2::ZERO,
+        };
+        let mut replay = record_with(ChunkMap::default(), Some(player.clone()), |_| {}, jetpack_and_dig, 180);
+        assert_eq!(replay.player.as_ref().map(|saved| (saved.pos, saved.inventory)), Some((player.pos, player.inventory)));
+        assert_eq!(verify(&replay), Ok(()));
+
+        replay.player = None;
+        assert!(verify(&replay).is_err());
+    }
+
+    #[test]
+    fn recording_still_saves() {
+        let storage = Storage::memory();
+        record_with(ChunkMap::default().saved_in(&storage, "World/0"), None, |_| {}, jetpack_and_dig, 180);
+        assert!(SavedPlayer::load(&storage, "World/0").is_ok());
+    }
+
+    #[test]
+    fn changed_input_diverges() {
+        let mut replay = record(jetpack_and_dig, 180);
+        replay.ticks[30].held.set(Action::Jump, false);
+
+        assert!(verify(&replay).is_err());
+    }
+
+    /// Replays checked into `replays/` are regression tests for the simulation.
+    #[test]
+    fn saved_replays() -> Result<(), Box<dyn Error>> {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("replays");
+        let Ok(entries) = std::fs::read_dir(dir) else {
+            return Ok(());
+        };
+
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == "replay") {
+                verify(&Replay::load(&path)?).map_err(|e| format!("{}: {e}", path.display()))?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,105 @@
+use bevy_ecs::system::Resource;
+
+use super::SaveError;
+
+/// One piece of a save (a region, or the player), small enough to write in a frame.
+pub type SaveStep = Box<dyn FnOnce() -> Result<(), SaveError> + Send + Sync>;
+
+/// A save being written without holding the game up. Natively it's written on a thread of its own,
+/// browsers don't have threads so it's written a few steps a frame instead.
+#[derive(Resource)]
+pub struct SaveInProgress {
+    #[cfg(not(target_family = "wasm"))]
+    thread: Option<std::thread::JoinHandle<Result<(), SaveError>>>,
+    #[cfg(target_family = "wasm")]
+    steps: std::collections::VecDeque<SaveStep>,
+}
+
+/// How long to spend writing a save each frame, in seconds, when it's not on a thread.
+#[cfg(target_family = "wasm")]
+const FRAME_BUDGET: f64 = 0.004;
+
+impl SaveInProgress {
+    /// Starts running `steps` in order, stopping at the first that fails.
+    pub fn start(steps: Vec<SaveStep>) -> SaveInProgress {
+        #[cfg(not(target_family = "wasm"))]
+        return SaveInProgress { thread: Some(std::thread::spawn(move || run(steps))) };
+        #[cfg(target_family = "wasm")]
+        return SaveInProgress { steps: steps.into() };
+    }
+
+    /// How the save went, once it's done.
+    #[cfg(not(target_family = "wasm"))]
+    pub fn poll(&mut self) -> Option<Result<(), SaveError>> {
+        match &self.thread {
+            Some(thread) if thread.is_finished() => Some(join(self.thread.take())),
+            Some(_) => None,
+            None => Some(Ok(())),
+        }
+    }
+
+    /// Writes this frame's share of the save, then how it went, once it's done.
+    #[cfg(target_family = "wasm")]
+    pub fn poll(&mut self) -> Option<Result<(), SaveError>> {
+        let started = macroquad::miniquad::date::now();
+        while let Some(step) = self.steps.pop_front() {
+            if let Err(e) = step() {
+                self.steps.clear();
+                return Some(Err(e));
+            }
+            if macroquad::miniquad::date::now() - started > FRAME_BUDGET {
+                break;
+            }
+        }
+        self.steps.is_empty().then_some(Ok(()))
+    }
+
+    /// Waits for the rest of the save to be written.
+    pub fn finish(mut self) -> Result<(), SaveError> {
+        #[cfg(not(target_family = "wasm"))]
+        return join(self.thread.take());
+        #[cfg(target_family = "wasm")]
+        return run(std::mem::take(&mut self.steps));
+    }
+}
+
+fn run(steps: impl IntoIterator<Item = SaveStep>) -> Result<(), SaveError> {
+    steps.into_iter().try_for_each(|step| step())
+}
+
+#[cfg(not(target_family = "wasm"))]
+fn join(thread: Option<std::thread::JoinHandle<Result<(), SaveError>>>) -> Result<(), SaveError> {
+    thread.map_or(Ok(()), |thread| {
+        thread.join().unwrap_or_else(|_| Err(SaveError::Write(String::from("the saving thread crashed"))))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+
+    #[test]
+    fn steps_run_in_order_until_one_fails() {
+        let ran = Arc::new(Mutex::new(Vec::new()));
+        let step = |i: u32, ok: bool| -> SaveStep {
+            let ran = ran.clone();
+            Box::new(move || {
+                ran.lock().unwrap().push(i);
+                if ok { Ok(()) } else { Err(SaveError::Write(format!("step {i}"))) }
+            })
+        };
+
+        let mut save = SaveInProgress::start(vec![step(0, true), step(1, true)]);
+        while save.poll().is_none() {
+            std::thread::yield_now();
+        }
+        assert_eq!(save.poll(), Some(Ok(())));
+        assert_eq!(*ran.lock().unwrap(), [0, 1]);
+
+        let save = SaveInProgress::start(vec![step(2, true), step(3, false), step(4, true)]);
+        assert_eq!(save.finish(), Err(SaveError::Write(String::from("step 3"))));
+        assert_eq!(*ran.lock().unwrap(), [0, 1, 2, 3]);
+    }
+}
@@ -0,0 +1,137 @@
+use std::error::Error;
+use std::fmt;
+
+use base64::prelude::BASE64_STANDARD;
+use base64::Engine as _;
+use bevy_ecs::system::Resource;
+use serde::{Deserialize, Serialize};
+
+pub mod background;
+pub mod scene;
+pub mod slots;
+mod storage;
+
+pub use storage::{MemoryStorage, Storage, StorageBackend};
+#[cfg(not(target_family = "wasm"))]
+pub use storage::FileStorage;
+
+/// Starts every record the game saves. Saves from before there was a header start with a length
+/// instead, and no save is ever long enough to start with these bytes.
+pub const MAGIC: [u8; 4] = *b"DIGs";
+
+/// Goes up whenever anything saved changes layout. Whatever reads a record keeps the layouts of
+/// older versions around and upgrades them, so every save stays loadable.
+///
+/// - 0: no header, from when the only thing saved was the one world, every chunk in full
+/// - 1: [`Header`], with worlds saved in slots and their chunks in regions
+pub const VERSION: u8 = 1;
+
+/// Why something couldn't be saved or loaded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SaveError {
+    /// Nothing has been saved.
+    Missing,
+    /// Something was saved, but it can't be read.
+    Corrupt(String),
+    /// Saved by a newer version of the game than this one.
+    Incompatible { version: u8 },
+    /// Storage couldn't be written to.
+    Write(String),
+}
+
+impl fmt::Display for SaveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SaveError::Missing => write!(f, "there's no save"),
+            SaveError::Corrupt(why) => write!(f, "the save is damaged ({why})"),
+            SaveError::Incompatible { version } => {
+                write!(f, "the save is from a newer version of the game ({version}, this is {VERSION})")
+            }
+            SaveError::Write(why) => write!(f, "couldn't write the save ({why})"),
+        }
+    }
+}
+
+impl Error for SaveError {}
+
+impl From<bincode::Error> for SaveError {
+    fn from(error: bincode::Error) -> Self {
+        SaveError::Corrupt(error.to_string())
+    }
+}
+
+/// The save couldn't be loaded, kept around while asking what to do about it.
+#[derive(Resource, Debug)]
+pub struct LoadFailed {
+    pub error: SaveError,
+    pub has_backup: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Header {
+    pub magic: [u8; 4],
+    pub version: u8,
+}
+
+impl Header {
+    pub fn current() -> Self {
+        Header { magic: MAGIC, version: VERSION }
+    }
+}
+
+/// A record read back out of storage, still in the layout of whichever version saved it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Record {
+    pub version: u8,
+    pub body: Vec<u8>,
+}
+
+/// `value` in the current layout with a header in front, ready for storage.
+pub fn encode<T: Serialize>(value: &T) -> Result<String, SaveError> {
+    let write_error = |e: bincode::Error| SaveError::Write(e.to_string());
+    let mut data = bincode::serialize(&Header::current()).map_err(write_error)?;
+    bincode::serialize_into(&mut data, value).map_err(write_error)?;
+    Ok(BASE64_STANDARD.encode(data))
+}
+
+/// Splits something written by [`encode`] (or before it existed) into its version and body.
+pub fn decode(data: &str) -> Result<Record, SaveError> {
+    let data = BASE64_STANDARD.decode(data).map_err(|e| SaveError::Corrupt(e.to_string()))?;
+
+    let header_size = bincode::serialized_size(&Header::current())? as usize;
+    match bincode::deserialize::<Header>(&data) {
+        Ok(header) if header.magic == MAGIC && header.version > VERSION => {
+            Err(SaveError::Incompatible { version: header.version })
+        }
+        Ok(header) if header.magic == MAGIC => Ok(Record {
+            version: header.version,
+            body: data[header_size..].to_vec(),
+        }),
+        _ => Ok(Record { version: 0, body: data }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn records_keep_their_version() {
+        let record = decode(&encode(&(1u8, String::from("dig"))).unwrap()).unwrap();
+        assert_eq!(record.version, VERSION);
+        assert_eq!(bincode::deserialize::<(u8, String)>(&record.body).unwrap(), (1, String::from("dig")));
+
+        // Saved before headers
+        let old = BASE64_STANDARD.encode(bincode::serialize(&vec![3u32, 4]).unwrap());
+        let record = decode(&old).unwrap();
+        assert_eq!(record.version, 0);
+        assert_eq!(bincode::deserialize::<Vec<u32>>(&record.body).unwrap(), vec![3, 4]);
+
+        assert!(matches!(decode("not base64!"), Err(SaveError::Corrupt(_))));
+
+        let mut future = bincode::serialize(&Header { magic: MAGIC, version: VERSION + 1 }).unwrap();
+        future.extend([1, 2, 3]);
+        let future = decode(&BASE64_STANDARD.encode(future));
+        assert_eq!(future, Err(SaveError::Incompatible { version: VERSION + 1 }));
+    }
+}
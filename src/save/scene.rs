@@ -0,0 +1,184 @@
+use bevy_ecs::component::Component;
+use bevy_ecs::entity::Entity;
+use bevy_ecs::query::With;
+use bevy_ecs::system::Resource;
+use bevy_ecs::world::{EntityRef, EntityWorldMut, Mut, World};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::entity::tile_map::entities_key;
+
+use super::{SaveError, Storage};
+
+/// Saved with the world and spawned again when it's loaded, along with whichever of its
+/// components are in the [`SaveRegistry`]. The rest are left behind.
+#[derive(Component, Debug, Default, Clone, Copy)]
+pub struct Saveable;
+
+type SaveFn = fn(&EntityRef) -> Option<Result<Vec<u8>, SaveError>>;
+type LoadFn = fn(&mut EntityWorldMut, &[u8]) -> Result<(), SaveError>;
+
+struct Registration {
+    name: &'static str,
+    save: SaveFn,
+    load: LoadFn,
+}
+
+/// The components that are saved on [`Saveable`] entities. Add to it with
+/// [`crate::app::App::register_saveable`].
+#[derive(Resource, Default)]
+pub struct SaveRegistry {
+    components: Vec<Registration>,
+}
+
+impl SaveRegistry {
+    /// Saves `C` under `name`, which has to stay the same for as long as there are saves with it in,
+    /// however the type is renamed or moved.
+    ///
+    /// # Panics
+    /// If something else is already saved under `name`.
+    pub fn register<C: Component + Serialize + DeserializeOwned>(&mut self, name: &'static str) {
+        assert!(self.components.iter().all(|registered| registered.name != name), "{name} is already saveable");
+        self.components.push(Registration {
+            name,
+            save: |entity| entity.get::<C>().map(|component| {
+                bincode::serialize(component).map_err(|e| SaveError::Write(e.to_string()))
+            }),
+            load: |entity, data| {
+                entity.insert(bincode::deserialize::<C>(data)?);
+                Ok(())
+            },
+        });
+    }
+}
+
+/// Every [`Saveable`] entity in a world, as it's saved.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SavedScene {
+    /// Each entity's components, by the names they were registered under.
+    entities: Vec<Vec<(String, Vec<u8>)>>,
+}
+
+impl SavedScene {
+    pub fn from_world(world: &mut World) -> Result<SavedScene, SaveError> {
+        let entities: Vec<Entity> = world.query_filtered::<Entity, With<Saveable>>().iter(world).collect();
+        let Some(registry) = world.get_resource::<SaveRegistry>() else {
+            return Ok(SavedScene { entities: vec![Vec::new(); entities.len()] });
+        };
+
+        let mut scene = SavedScene::default();
+        for entity in entities {
+            let entity = world.entity(entity);
+            let mut components = Vec::new();
+            for registered in &registry.components {
+                if let Some(data) = (registered.save)(&entity) {
+                    components.push((registered.name.to_owned(), data?));
+                }
+            }
+            scene.entities.push(components);
+        }
+        Ok(scene)
+    }
+
+    /// Spawns every entity in the scene. If any of them can't be read, none of them are spawned.
+    pub fn spawn(&self, world: &mut World) -> Result<(), SaveError> {
+        let mut spawned = Vec::new();
+        let result = self.spawn_into(world, &mut spawned);
+        if result.is_err() {
+            for entity in spawned {
+                world.despawn(entity);
+            }
+        }
+        result
+    }
+
+    fn spawn_into(&self, world: &mut World, spawned: &mut Vec<Entity>) -> Result<(), SaveError> {
+        world.init_resource::<SaveRegistry>();
+        world.resource_scope(|world, registry: Mut<SaveRegistry>| {
+            for components in &self.entities {
+                let mut entity = world.spawn(Saveable);
+                spawned.push(entity.id());
+                for (name, data) in components {
+                    match registry.components.iter().find(|registered| registered.name == name) {
+                        Some(registered) => (registered.load)(&mut entity, data)?,
+                        // Saved by a version with something this one doesn't have
+                        None => println!("Skipping saved {name}, nothing's registered under that name"),
+                    }
+                }
+            }
+            Ok(())
+        })
+    }
+
+    /// The entities saved with the world in `slot`.
+    pub fn load(storage: &Storage, slot: &str) -> Result<SavedScene, SaveError> {
+        let data = storage.lock()?.get(&entities_key(slot)).ok_or(SaveError::Missing)?;
+        let record = super::decode(&data)?;
+        match record.version {
+            1 => Ok(bincode::deserialize(&record.body)?),
+            version => Err(SaveError::Incompatible { version }),
+        }
+    }
+
+    pub fn save(&self, storage: &Storage, slot: &str) -> Result<(), SaveError> {
+        storage.lock()?.set(&entities_key(slot), &super::encode(self)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Component, Debug, Clone, PartialEq, Serialize, Deserialize)]
+    struct Sign(String);
+
+    #[derive(Component, Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+    struct Fuel(u32);
+
+    /// Not registered, so it isn't saved.
+    #[derive(Component)]
+    struct Scratch;
+
+    #[test]
+    fn saveable_entities_come_back() -> Result<(), SaveError> {
+        let mut registry = SaveRegistry::default();
+        registry.register::<Sign>("Sign");
+        registry.register::<Fuel>("Fuel");
+
+        let mut world = World::new();
+        world.insert_resource(registry);
+        world.spawn((Saveable, Sign(String::from("Dig here")), Fuel(3), Scratch));
+        world.spawn((Saveable, Fuel(7)));
+        world.spawn(Sign(String::from("Not saved")));
+
+        let storage = Storage::memory();
+        SavedScene::from_world(&mut world)?.save(&storage, "World/0")?;
+
+        let mut loaded = World::new();
+        let mut registry = SaveRegistry::default();
+        registry.register::<Fuel>("Fuel");
+        registry.register::<Sign>("Sign");
+        loaded.insert_resource(registry);
+        SavedScene::load(&storage, "World/0")?.spawn(&mut loaded)?;
+
+        let mut entities: Vec<(Option<Sign>, Option<Fuel>, bool)> = loaded
+            .query_filtered::<(Option<&Sign>, Option<&Fuel>, Option<&Scratch>), With<Saveable>>()
+            .iter(&loaded)
+            .map(|(sign, fuel, scratch)| (sign.cloned(), fuel.copied(), scratch.is_some()))
+            .collect();
+        entities.sort_by_key(|(_, fuel, _)| fuel.map(|fuel| fuel.0));
+        assert_eq!(entities, [
+            (Some(Sign(String::from("Dig here"))), Some(Fuel(3)), false),
+            (None, Some(Fuel(7)), false),
+        ]);
+        assert_eq!(loaded.query::<&Sign>().iter(&loaded).count(), 1);
+
+        // A component this version doesn't know about is skipped, but one that can't be read stops the lot
+        let unknown = SavedScene { entities: vec![vec![(String::from("Lava"), vec![1])]] };
+        unknown.spawn(&mut loaded)?;
+        let broken = SavedScene { entities: vec![vec![], vec![(String::from("Sign"), vec![200])]] };
+        assert!(matches!(broken.spawn(&mut loaded), Err(SaveError::Corrupt(_))));
+        assert_eq!(loaded.query::<&Saveable>().iter(&loaded).count(), 3);
+        Ok(())
+    }
+}
@@ -0,0 +1,178 @@
+use bevy_ecs::system::Resource;
+use serde::{Deserialize, Serialize};
+
+use crate::entity::tile_map::ChunkMap;
+
+use super::{SaveError, Storage};
+
+/// Where the list of worlds is kept.
+static SLOTS_KEY: &str = "Slots";
+
+/// Where worlds were saved before there was more than one. A save found there becomes the first slot.
+pub(crate) static FIRST_SLOT_KEY: &str = "ChunkMap";
+
+/// One saved world.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct WorldSlot {
+    /// Where the world is saved. This stays the same when it's renamed.
+    pub key: String,
+    pub name: String,
+    /// What it was generated from, which isn't known for worlds from before slots.
+    pub seed: Option<u32>,
+    /// When it was last saved, in seconds since 1970.
+    pub last_played: f64,
+}
+
+/// The world being played, or about to be.
+#[derive(Resource, Debug, Clone)]
+pub struct CurrentSlot(pub String);
+
+#[derive(Default, Serialize, Deserialize)]
+struct Slots {
+    /// Counts up, so a new slot never lands on the keys of a deleted one's leftovers.
+    next_id: u32,
+    slots: Vec<WorldSlot>,
+}
+
+/// Every world, most recently played first.
+pub fn list(storage: &Storage) -> Result<Vec<WorldSlot>, SaveError> {
+    let mut slots = read_slots(storage)?.slots;
+    slots.sort_by(|a, b| b.last_played.total_cmp(&a.last_played));
+    Ok(slots)
+}
+
+pub fn get(storage: &Storage, key: &str) -> Result<WorldSlot, SaveError> {
+    read_slots(storage)?.slots.into_iter().find(|slot| slot.key == key).ok_or(SaveError::Missing)
+}
+
+/// Adds an empty slot, which is generated from `seed` when it's first played.
+pub fn create(storage: &Storage, name: &str, seed: u32) -> Result<WorldSlot, SaveError> {
+    let mut slots = read_slots(storage)?;
+    let slot = slots.add(name, Some(seed));
+    write_slots(storage, &slots)?;
+    Ok(slot)
+}
+
+pub fn rename(storage: &Storage, key: &str, name: &str) -> Result<(), SaveError> {
+    let mut slots = read_slots(storage)?;
+    slots.find(key)?.name = name.to_owned();
+    write_slots(storage, &slots)
+}
+
+/// Copies the world in `key` to a new slot called `name`.
+pub fn duplicate(storage: &Storage, key: &str, name: &str) -> Result<WorldSlot, SaveError> {
+    let mut slots = read_slots(storage)?;
+    let seed = slots.find(key)?.seed;
+    let copy = slots.add(name, seed);
+
+    // Never played, so there's nothing to copy
+    match ChunkMap::copy_saved(storage, key, &copy.key) {
+        Ok(()) | Err(SaveError::Missing) => {}
+        Err(error) => return Err(error),
+    }
+    write_slots(storage, &slots)?;
+    Ok(copy)
+}
+
+/// Deletes the world in `key`, backup and all.
+pub fn delete(storage: &Storage, key: &str) -> Result<(), SaveError> {
+    let mut slots = read_slots(storage)?;
+    slots.slots.retain(|slot| slot.key != key);
+    ChunkMap::delete_saved(storage, key)?;
+    write_slots(storage, &slots)
+}
+
+/// Marks the world in `key` as played just now.
+pub fn played(storage: &Storage, key: &str) -> Result<(), SaveError> {
+    let mut slots = read_slots(storage)?;
+    if let Ok(slot) = slots.find(key) {
+        slot.last_played = now();
+        write_slots(storage, &slots)?;
+    }
+    Ok(())
+}
+
+impl Slots {
+    fn add(&mut self, name: &str, seed: Option<u32>) -> WorldSlot {
+        let slot = WorldSlot {
+            key: format!("World/{}", self.next_id),
+            name: name.to_owned(),
+            seed,
+            last_played: now(),
+        };
+        self.next_id += 1;
+        self.slots.push(slot.clone());
+        slot
+    }
+
+    fn find(&mut self, key: &str) -> Result<&mut WorldSlot, SaveError> {
+        self.slots.iter_mut().find(|slot| slot.key == key).ok_or(SaveError::Missing)
+    }
+}
+
+fn read_slots(storage: &Storage) -> Result<Slots, SaveError> {
+    let data = storage.lock()?.get(SLOTS_KEY);
+    match data {
+        Some(data) => {
+            let record = super::decode(&data)?;
+            match record.version {
+                1 => Ok(bincode::deserialize(&record.body)?),
+                version => Err(SaveError::Incompatible { version }),
+            }
+        }
+        None => {
+            let mut slots = Slots::default();
+            if storage.lock()?.get(FIRST_SLOT_KEY).is_some() {
+                slots.slots.push(WorldSlot {
+                    key: FIRST_SLOT_KEY.to_owned(),
+                    name: String::from("World"),
+                    seed: None,
+                    last_played: now(),
+                });
+            }
+            Ok(slots)
+        }
+    }
+}
+
+fn write_slots(storage: &Storage, slots: &Slots) -> Result<(), SaveError> {
+    storage.lock()?.set(SLOTS_KEY, &super::encode(slots)?)
+}
+
+fn now() -> f64 {
+    macroquad::miniquad::date::now()
+}
+
+#[cfg(test)]
+mod tests {
+    use macroquad::math::{ivec2, uvec2};
+
+    use crate::position::ChunkPos;
+    use crate::tile::TileId;
+
+    use super::*;
+
+    #[test]
+    fn worlds_copy_rename_and_delete() -> Result<(), SaveError> {
+        let storage = Storage::memory();
+        let first = create(&storage, "First", 5)?;
+        let mut map = ChunkMap::new(5).saved_in(&storage, first.key.clone());
+        map.get_mut(ChunkPos(ivec2(0, 0))).set(uvec2(0, 0), TileId::GoldOre);
+        map.save()?;
+
+        // The copy is a world of its own, with the same tiles and seed
+        let copy = duplicate(&storage, &first.key, "Copy")?;
+        rename(&storage, &first.key, "Renamed")?;
+        assert_eq!(get(&storage, &copy.key)?.seed, Some(5));
+        assert_eq!(ChunkMap::saved_size(&storage, &copy.key), ChunkMap::saved_size(&storage, &first.key));
+        let mut copied = ChunkMap::load(&storage, &copy.key)?;
+        assert_eq!(copied.get(ChunkPos(ivec2(0, 0))).get(uvec2(0, 0)), Some(TileId::GoldOre));
+
+        delete(&storage, &first.key)?;
+        let names = list(&storage)?.into_iter().map(|slot| slot.name).collect::<Vec<_>>();
+        assert_eq!(names, ["Copy"]);
+        // Nothing of it is left behind, backup included
+        assert!(storage.lock()?.keys().iter().all(|key| !key.starts_with(&first.key)));
+        Ok(())
+    }
+}
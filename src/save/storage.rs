@@ -0,0 +1,225 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, LazyLock, Mutex, MutexGuard};
+
+use bevy_ecs::system::Resource;
+
+use super::SaveError;
+
+/// Somewhere saves are kept, as strings under keys like `World/3/region/0,-1`.
+pub trait StorageBackend: Send {
+    fn get(&self, key: &str) -> Option<String>;
+    fn set(&mut self, key: &str, value: &str) -> Result<(), SaveError>;
+    /// Removing a key that isn't there is fine.
+    fn remove(&mut self, key: &str) -> Result<(), SaveError>;
+    fn keys(&self) -> Vec<String>;
+}
+
+/// A handle on a [`StorageBackend`], shared by everything that saves to it.
+#[derive(Resource, Clone)]
+pub struct Storage(Arc<Mutex<dyn StorageBackend>>);
+
+static PLATFORM: LazyLock<Storage> = LazyLock::new(platform_storage);
+
+impl Storage {
+    pub fn new(backend: impl StorageBackend + 'static) -> Storage {
+        Storage(Arc::new(Mutex::new(backend)))
+    }
+
+    /// The game's own storage: files in the user's data folder on native, local storage in a browser.
+    pub fn platform() -> Storage {
+        PLATFORM.clone()
+    }
+
+    /// Storage that's gone when the last handle on it is, for tests and for worlds that aren't saved.
+    pub fn memory() -> Storage {
+        Storage::new(MemoryStorage::default())
+    }
+
+    pub fn lock(&self) -> Result<MutexGuard<'_, dyn StorageBackend + 'static>, SaveError> {
+        self.0.lock().map_err(|e| SaveError::Write(e.to_string()))
+    }
+}
+
+impl fmt::Debug for Storage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Storage")
+    }
+}
+
+#[derive(Default)]
+pub struct MemoryStorage {
+    values: HashMap<String, String>,
+}
+
+impl StorageBackend for MemoryStorage {
+    fn get(&self, key: &str) -> Option<String> {
+        self.values.get(key).cloned()
+    }
+    fn set(&mut self, key: &str, value: &str) -> Result<(), SaveError> {
+        self.values.insert(key.to_owned(), value.to_owned());
+        Ok(())
+    }
+    fn remove(&mut self, key: &str) -> Result<(), SaveError> {
+        self.values.remove(key);
+        Ok(())
+    }
+    fn keys(&self) -> Vec<String> {
+        self.values.keys().cloned().collect()
+    }
+}
+
+#[cfg(not(target_family = "wasm"))]
+pub use files::FileStorage;
+
+#[cfg(not(target_family = "wasm"))]
+mod files {
+    use std::fs;
+    use std::io::ErrorKind;
+    use std::path::PathBuf;
+
+    use super::{SaveError, StorageBackend};
+
+    /// A file for each key, all in one folder.
+    pub struct FileStorage {
+        dir: PathBuf,
+    }
+
+    impl FileStorage {
+        pub fn new(dir: impl Into<PathBuf>) -> Result<FileStorage, SaveError> {
+            let dir = dir.into();
+            fs::create_dir_all(&dir).map_err(|e| SaveError::Write(format!("{}: {e}", dir.display())))?;
+            Ok(FileStorage { dir })
+        }
+
+        /// Where saves go by default: a `dig` folder wherever the system keeps programs' data.
+        pub fn data_dir() -> PathBuf {
+            let home = |path: &str| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(path));
+            let base = if cfg!(windows) {
+                std::env::var_os("APPDATA").map(PathBuf::from)
+            } else if cfg!(target_os = "macos") {
+                home("Library/Application Support")
+            } else {
+                std::env::var_os("XDG_DATA_HOME").map(PathBuf::from).or_else(|| home(".local/share"))
+            };
+            base.unwrap_or_else(|| PathBuf::from("saves")).join("dig")
+        }
+
+        fn path(&self, key: &str) -> PathBuf {
+            // Keys have slashes in, but they're all files in the one folder
+            let name = key.replace('%', "%25").replace('/', "%2F");
+            self.dir.join(format!("{name}.sav"))
+        }
+    }
+
+    impl StorageBackend for FileStorage {
+        fn get(&self, key: &str) -> Option<String> {
+            fs::read_to_string(self.path(key)).ok()
+        }
+
+        fn set(&mut self, key: &str, value: &str) -> Result<(), SaveError> {
+            // Written next to it and moved over it, so a crash halfway leaves the old one whole
+            let path = self.path(key);
+            let partial = path.with_extension("partial");
+            fs::write(&partial, value)
+                .and_then(|()| fs::rename(&partial, &path))
+                .map_err(|e| SaveError::Write(format!("{}: {e}", path.display())))
+        }
+
+        fn remove(&mut self, key: &str) -> Result<(), SaveError> {
+            match fs::remove_file(self.path(key)) {
+                Err(e) if e.kind() != ErrorKind::NotFound => Err(SaveError::Write(e.to_string())),
+                _ => Ok(()),
+            }
+        }
+
+        fn keys(&self) -> Vec<String> {
+            let Ok(entries) = fs::read_dir(&self.dir) else { return Vec::new() };
+            entries
+                .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+                .filter_map(|name| Some(name.strip_suffix(".sav")?.replace("%2F", "/").replace("%25", "%")))
+                .collect()
+        }
+    }
+}
+
+/// The browser's local storage.
+#[cfg(target_family = "wasm")]
+pub struct BrowserStorage;
+
+#[cfg(target_family = "wasm")]
+impl StorageBackend for BrowserStorage {
+    fn get(&self, key: &str) -> Option<String> {
+        quad_storage::STORAGE.lock().ok()?.get(key)
+    }
+    fn set(&mut self, key: &str, value: &str) -> Result<(), SaveError> {
+        quad_storage::STORAGE.lock().map_err(|e| SaveError::Write(e.to_string()))?.set(key, value);
+        Ok(())
+    }
+    fn remove(&mut self, key: &str) -> Result<(), SaveError> {
+        quad_storage::STORAGE.lock().map_err(|e| SaveError::Write(e.to_string()))?.remove(key);
+        Ok(())
+    }
+    fn keys(&self) -> Vec<String> {
+        let Ok(storage) = quad_storage::STORAGE.lock() else { return Vec::new() };
+        (0..storage.len()).filter_map(|i| storage.key(i)).collect()
+    }
+}
+
+#[cfg(target_family = "wasm")]
+fn platform_storage() -> Storage {
+    Storage::new(BrowserStorage)
+}
+
+#[cfg(not(target_family = "wasm"))]
+fn platform_storage() -> Storage {
+    let dir = FileStorage::data_dir();
+    let mut files = match FileStorage::new(&dir) {
+        Ok(files) => files,
+        Err(e) => {
+            println!("Couldn't use {} for saves, nothing will be kept: {e}", dir.display());
+            return Storage::memory();
+        }
+    };
+
+    // Saves used to go in quad_storage's file next to the game, so bring those along the first time
+    if files.keys().is_empty() {
+        if let Ok(old) = quad_storage::STORAGE.lock() {
+            for key in (0..old.len()).filter_map(|i| old.key(i)) {
+                if let Some(value) = old.get(&key) {
+                    if let Err(e) = files.set(&key, &value) {
+                        println!("Couldn't move {key} out of the old save: {e}");
+                    }
+                }
+            }
+        }
+    }
+    Storage::new(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn file_storage_keeps_keys_apart() {
+        let dir = std::env::temp_dir().join(format!("dig-storage-test-{}", std::process::id()));
+        let mut files = FileStorage::new(&dir).unwrap();
+
+        // A key and the keys "under" it are separate files, not a file and a folder
+        files.set("World/1", "map").unwrap();
+        files.set("World/1/region/0,-1", "region").unwrap();
+        files.set("100%", "odd").unwrap();
+        assert_eq!(files.get("World/1").as_deref(), Some("map"));
+        assert_eq!(files.get("World/1/region/0,-1").as_deref(), Some("region"));
+        let mut keys = files.keys();
+        keys.sort();
+        assert_eq!(keys, ["100%", "World/1", "World/1/region/0,-1"]);
+
+        files.remove("World/1").unwrap();
+        files.remove("World/1").unwrap();
+        assert_eq!(files.get("World/1"), None);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
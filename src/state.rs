@@ -0,0 +1,56 @@
+use bevy_ecs::system::{Res, Resource};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum GameState {
+    #[default]
+    MainMenu,
+    /// The world is being loaded or generated. Nothing in the world exists until this is left.
+    Loading,
+    Playing,
+    /// The world is still drawn but doesn't tick.
+    Paused,
+    /// The save couldn't be loaded, waiting on whether to restore the backup.
+    LoadFailed,
+}
+
+/// The current [`GameState`]. Changes asked for with [`State::set`] happen between
+/// `PreUpdate` and `FixedUpdate`, running the `OnExit` and `OnEnter` schedules on the way.
+#[derive(Resource, Debug, Default)]
+pub struct State {
+    current: GameState,
+    next: Option<GameState>,
+}
+
+impl State {
+    pub fn new(state: GameState) -> Self {
+        State {
+            current: state,
+            next: None,
+        }
+    }
+
+    pub fn get(&self) -> GameState {
+        self.current
+    }
+
+    pub fn set(&mut self, next: GameState) {
+        self.next = Some(next);
+    }
+
+    /// Moves to the queued state, returning the one being left and the one being entered.
+    pub(crate) fn transition(&mut self) -> Option<(GameState, GameState)> {
+        let next = self.next.take()?;
+        let previous = std::mem::replace(&mut self.current, next);
+        Some((previous, next))
+    }
+}
+
+/// Run condition for systems that only run in `state`.
+pub fn in_state(state: GameState) -> impl FnMut(Res<State>) -> bool + Clone {
+    move |current: Res<State>| current.get() == state
+}
+
+/// Run condition for systems that need the world to exist, paused or not.
+pub fn in_game(state: Res<State>) -> bool {
+    matches!(state.get(), GameState::Playing | GameState::Paused)
+}
@@ -3,6 +3,8 @@ pub use tile_full::Tile as Tile;
 pub use tile_id::TileId as TileId;
 
 mod tile_full {
+    use macroquad::color::{Color, WHITE};
+
     use crate::physics2::CollisionResult;
 
     #[derive(Default, Clone, Copy, PartialEq)]
@@ -27,10 +29,13 @@ mod tile_full {
         pub breakable: Breakable,
         pub name: &'static str,
         pub physicality: TilePhysicality,
+        /// Multiplied into the sprite, so one sprite can stand in for more than one tile.
+        pub tint: Color,
     }
     
     pub(super) const EMPTY_TILE: Tile = Tile {
         sprite: None,
+        tint: WHITE,
         breakable: Breakable::Indestructable,
         name: "EMPTY",
         physicality: TilePhysicality::Empty
@@ -51,12 +56,15 @@ mod tile_full {
 }
 
 mod tile_id {
+    use macroquad::color::Color;
     use serde::{Deserialize, Serialize};
 
     use crate::tile::tile_full::{Tile, EMPTY_TILE};
 
     use super::tile_full::{Breakable, TilePhysicality};
-    #[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, Default)]
+    /// Saves store tiles by where they are in this list, so new ones go on the end.
+    /// Moving one means bumping [`crate::save::VERSION`] and upgrading older saves.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize, Default)]
     pub enum TileId {
         #[default]
         Air = 0,
@@ -64,6 +72,10 @@ mod tile_id {
         WoodPlanks = 2,
         WoodLog = 3,
         GenericOre = 4,
+        Stone = 5,
+        IronOre = 6,
+        GoldOre = 7,
+        Grass = 8,
     }
     
     impl TileId {
@@ -82,25 +94,58 @@ mod tile_id {
                     breakable: WithTime(1.0),
                     name: "Dirt",
                     physicality: Solid,
-                    sprite: Some(9)
+                    sprite: Some(9),
+                    ..EMPTY_TILE
+                },
+                Grass => &T {
+                    breakable: WithTime(1.0),
+                    name: "Grass",
+                    physicality: Solid,
+                    sprite: Some(25),
+                    ..EMPTY_TILE
                 },
                 GenericOre => &T {
                     breakable: WithTime(2.0),
                     name: "Ore",
                     physicality: Solid,
-                    sprite: Some(32)
+                    sprite: Some(32),
+                    ..EMPTY_TILE
                 },
                 WoodPlanks => &T {
                     breakable: WithTime(2.0),
                     name: "Wood",
                     physicality: Solid,
-                    sprite: Some(33)
+                    sprite: Some(33),
+                    ..EMPTY_TILE
                 },
                 WoodLog => &T {
                     breakable: WithTime(2.5),
                     name: "Wood Log",
                     physicality: Solid,
-                    sprite: Some(34)
+                    sprite: Some(34),
+                    ..EMPTY_TILE
+                },
+                IronOre => &T {
+                    breakable: WithTime(3.0),
+                    name: "Iron Ore",
+                    physicality: Solid,
+                    sprite: Some(32),
+                    tint: Color { r: 0.75, g: 0.75, b: 0.9, a: 1.0 },
+                },
+                GoldOre => &T {
+                    breakable: WithTime(4.0),
+                    name: "Gold Ore",
+                    physicality: Solid,
+                    sprite: Some(32),
+                    tint: Color { r: 1.0, g: 0.85, b: 0.4, a: 1.0 },
+                },
+                Stone => &T {
+                    breakable: WithTime(3.0),
+                    name: "Stone",
+                    physicality: Solid,
+                    // There's no stone sprite yet, so it's darkened dirt
+                    sprite: Some(9),
+                    tint: Color { r: 0.55, g: 0.5, b: 0.6, a: 1.0 },
                 }
             }
         }
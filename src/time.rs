@@ -0,0 +1,78 @@
+use bevy_ecs::system::Resource;
+
+/// Simulation tick rate. Everything on the `FixedUpdate` schedule sees exactly this delta.
+pub const FIXED_TIMESTEP: f32 = 1.0 / 60.0;
+
+/// Frames longer than this are clamped, so a stall (window drag, tab switch)
+/// doesn't make us run hundreds of fixed ticks to catch up.
+const MAX_FRAME_TIME: f32 = 0.25;
+
+#[derive(Resource, Debug, Clone)]
+pub struct Time {
+    delta: f32,
+    fixed_delta: f32,
+    accumulator: f32,
+    elapsed: f64,
+    ticks: u64,
+}
+
+impl Time {
+    pub fn new(fixed_delta: f32) -> Self {
+        Time {
+            delta: 0.0,
+            fixed_delta,
+            accumulator: 0.0,
+            elapsed: 0.0,
+            ticks: 0,
+        }
+    }
+
+    /// Length of the last rendered frame, in seconds.
+    pub fn delta(&self) -> f32 {
+        self.delta
+    }
+
+    /// Length of one simulation tick, in seconds.
+    pub fn fixed_delta(&self) -> f32 {
+        self.fixed_delta
+    }
+
+    /// How far we are between the last simulation tick and the next one, in `0.0..1.0`.
+    /// Rendering uses this to interpolate between the previous and current positions.
+    pub fn alpha(&self) -> f32 {
+        self.accumulator / self.fixed_delta
+    }
+
+    /// Total simulated time, in seconds.
+    pub fn elapsed(&self) -> f64 {
+        self.elapsed
+    }
+
+    /// Number of simulation ticks run so far.
+    pub fn ticks(&self) -> u64 {
+        self.ticks
+    }
+
+    pub(crate) fn advance(&mut self, frame_time: f32) {
+        self.delta = frame_time.clamp(0.0, MAX_FRAME_TIME);
+        self.accumulator += self.delta;
+    }
+
+    /// Consumes one tick from the accumulator, returning whether there was one to consume.
+    pub(crate) fn expend(&mut self) -> bool {
+        if self.accumulator >= self.fixed_delta {
+            self.accumulator -= self.fixed_delta;
+            self.elapsed += self.fixed_delta as f64;
+            self.ticks += 1;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+impl Default for Time {
+    fn default() -> Self {
+        Time::new(FIXED_TIMESTEP)
+    }
+}
@@ -0,0 +1,161 @@
+use macroquad::color::Color;
+use noise::{NoiseFn, Perlin};
+use serde::{Deserialize, Serialize};
+
+use crate::tile::TileId;
+
+use super::caves::Caverns;
+use super::ores::{Vein, VEINS};
+
+/// Roughly how many tiles wide a biome is.
+const BIOME_WIDTH: f64 = 160.0;
+
+/// What a stretch of the world is like, from the sky down. Picked by column, so a biome goes all the way down.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Biome {
+    /// Tall hills of bare rock, with iron close to the surface.
+    Highlands,
+    /// Rolling grassy hills with trees.
+    Forest,
+    /// Low, gently rolling grass.
+    Plains,
+    /// Sunken ground over huge caverns, which is where the gold is.
+    DeepCaverns,
+}
+
+/// The tiles a biome's ground is made of, top to bottom. Past the soil it's stone and ore.
+#[derive(Debug, Clone, Copy)]
+pub struct Palette {
+    /// The very top tile of the ground.
+    pub top: TileId,
+    /// What's under the top.
+    pub soil: TileId,
+    /// How many tiles deep the top and soil go on average.
+    pub soil_depth: f64,
+}
+
+/// Each biome in the order they blend into each other, with where each one sits on the biome noise.
+/// Neighbours in this list are neighbours in the world.
+const BIOMES: [(Biome, f64); 4] = [
+    (Biome::Highlands, -0.3),
+    (Biome::Forest, -0.1),
+    (Biome::Plains, 0.1),
+    (Biome::DeepCaverns, 0.3),
+];
+
+impl Biome {
+    /// How far, in tiles, this biome's ground sits below [`super::SURFACE_ROW`] on average.
+    pub fn ground_offset(self) -> f64 {
+        match self {
+            Biome::Highlands => -6.0,
+            Biome::Forest => -1.0,
+            Biome::Plains => 0.0,
+            Biome::DeepCaverns => 4.0,
+        }
+    }
+
+    /// How far, in tiles, hills rise above and valleys sink below the average.
+    pub fn hill_height(self) -> f64 {
+        match self {
+            Biome::Highlands => 10.0,
+            Biome::Forest => 6.0,
+            Biome::Plains => 3.0,
+            Biome::DeepCaverns => 2.0,
+        }
+    }
+
+    pub fn palette(self) -> Palette {
+        match self {
+            Biome::Highlands => Palette { top: TileId::Stone, soil: TileId::Stone, soil_depth: 1.0 },
+            Biome::Forest => Palette { top: TileId::Grass, soil: TileId::Dirt, soil_depth: 5.0 },
+            Biome::Plains => Palette { top: TileId::Grass, soil: TileId::Dirt, soil_depth: 4.0 },
+            Biome::DeepCaverns => Palette { top: TileId::Dirt, soil: TileId::Dirt, soil_depth: 3.0 },
+        }
+    }
+
+    /// Which ores turn up under this biome, commonest first.
+    pub fn ores(self) -> &'static [Vein] {
+        const HIGHLAND_VEINS: [Vein; 3] = [
+            Vein { tile: TileId::GenericOre, min_depth: 3, threshold: 0.62, size: 6.0 },
+            Vein { tile: TileId::IronOre, min_depth: 6, threshold: 0.58, size: 6.0 },
+            Vein { tile: TileId::GoldOre, min_depth: 50, threshold: 0.74, size: 4.0 },
+        ];
+        const CAVERN_VEINS: [Vein; 3] = [
+            Vein { tile: TileId::GenericOre, min_depth: 3, threshold: 0.6, size: 6.0 },
+            Vein { tile: TileId::IronOre, min_depth: 20, threshold: 0.65, size: 5.0 },
+            Vein { tile: TileId::GoldOre, min_depth: 25, threshold: 0.64, size: 5.0 },
+        ];
+
+        match self {
+            Biome::Highlands => &HIGHLAND_VEINS,
+            Biome::Forest | Biome::Plains => &VEINS,
+            Biome::DeepCaverns => &CAVERN_VEINS,
+        }
+    }
+
+    pub fn caverns(self) -> Caverns {
+        match self {
+            Biome::DeepCaverns => Caverns { min_depth: 12, threshold: 0.15 },
+            _ => Caverns::default(),
+        }
+    }
+
+    /// Whether trees grow here.
+    pub fn has_trees(self) -> bool {
+        self == Biome::Forest
+    }
+
+    /// The colour of the sky, or whatever's behind the tiles.
+    pub fn background(self) -> Color {
+        match self {
+            Biome::Highlands => Color::from_hex(0xa7b4c2),
+            Biome::Forest => Color::from_hex(0x9fd4a8),
+            Biome::Plains => Color::from_hex(0x9fd3f0),
+            Biome::DeepCaverns => Color::from_hex(0x6e5a7e),
+        }
+    }
+}
+
+/// Picks the biome for each column out of slow noise, and blends numbers between biomes so the
+/// ground doesn't jump at the border.
+#[derive(Clone, Debug)]
+pub struct Biomes {
+    noise: Perlin,
+}
+
+impl Biomes {
+    pub fn new(seed: u32) -> Self {
+        Biomes { noise: Perlin::new(seed) }
+    }
+
+    fn sample(&self, x: i32) -> f64 {
+        self.noise.get([(x as f64 + 0.5) / BIOME_WIDTH, 0.5])
+    }
+
+    /// The biome column `x` is in.
+    pub fn biome_at(&self, x: i32) -> Biome {
+        let noise = self.sample(x);
+
+        // Closest to its spot on the noise wins
+        BIOMES.iter()
+            .min_by(|(_, a), (_, b)| (a - noise).abs().total_cmp(&(b - noise).abs()))
+            .map(|&(biome, _)| biome)
+            .unwrap()
+    }
+
+    /// `value` of the biome at column `x`, fading into the neighbouring biome's towards the border.
+    pub fn blend(&self, x: i32, value: impl Fn(Biome) -> f64) -> f64 {
+        let noise = self.sample(x);
+
+        let Some(i) = BIOMES.iter().position(|&(_, at)| noise < at) else {
+            return value(BIOMES[BIOMES.len() - 1].0);
+        };
+        if i == 0 {
+            return value(BIOMES[0].0);
+        }
+
+        let ((low, low_at), (high, high_at)) = (BIOMES[i - 1], BIOMES[i]);
+        let t = (noise - low_at) / (high_at - low_at);
+        value(low) * (1.0 - t) + value(high) * t
+    }
+}
@@ -0,0 +1,67 @@
+use macroquad::math::IVec2;
+use noise::{Fbm, MultiFractal, NoiseFn, Perlin};
+
+use super::layer_seed;
+
+/// Caves start this many tiles below the surface, so the ground isn't riddled with holes.
+const CAVE_MIN_DEPTH: i32 = 6;
+/// Tunnels follow the noise's zero line, this close to it counts as inside.
+const TUNNEL_WIDTH: f64 = 0.06;
+/// Tunnels get wider going down, up to this many times [`TUNNEL_WIDTH`].
+const TUNNEL_MAX_WIDENING: f64 = 2.0;
+/// How many tiles of depth it takes tunnels to widen by another [`TUNNEL_WIDTH`].
+const TUNNEL_WIDENING_DEPTH: f64 = 60.0;
+/// Tunnels wander more sideways than up and down, roughly this many tiles per bend.
+const TUNNEL_LENGTH: f64 = 32.0;
+const TUNNEL_HEIGHT: f64 = 16.0;
+const CAVERN_SIZE: f64 = 40.0;
+
+/// Where big open caverns show up, which depends on the biome.
+#[derive(Clone, Copy, Debug)]
+pub struct Caverns {
+    /// Caverns only show up this deep.
+    pub min_depth: i32,
+    /// Above this the cavern noise is hollow. Lower is roomier.
+    pub threshold: f64,
+}
+
+impl Default for Caverns {
+    fn default() -> Self {
+        Caverns { min_depth: 40, threshold: 0.35 }
+    }
+}
+
+/// Where the ground is hollowed out: winding tunnels everywhere underground, and caverns deep down.
+#[derive(Clone, Debug)]
+pub struct Caves {
+    tunnels: Perlin,
+    caverns: Fbm<Perlin>,
+}
+
+impl Caves {
+    pub fn new(seed: u32) -> Self {
+        Caves {
+            tunnels: Perlin::new(layer_seed(seed, 0)),
+            caverns: Fbm::<Perlin>::new(layer_seed(seed, 1))
+                .set_octaves(3)
+                .set_frequency(1.0 / CAVERN_SIZE),
+        }
+    }
+
+    /// Whether `tile`, `depth` tiles below the surface, is part of a cave.
+    pub fn is_cave(&self, tile: IVec2, depth: i32, caverns: Caverns) -> bool {
+        if depth < CAVE_MIN_DEPTH {
+            return false;
+        }
+
+        // Offset by half a tile, Perlin noise is zero on every whole number
+        let (x, y) = (tile.x as f64 + 0.5, tile.y as f64 + 0.5);
+
+        let widening = (1.0 + (depth - CAVE_MIN_DEPTH) as f64 / TUNNEL_WIDENING_DEPTH).min(TUNNEL_MAX_WIDENING);
+        if self.tunnels.get([x / TUNNEL_LENGTH, y / TUNNEL_HEIGHT]).abs() < TUNNEL_WIDTH * widening {
+            return true;
+        }
+
+        depth >= caverns.min_depth && self.caverns.get([x, y]) > caverns.threshold
+    }
+}
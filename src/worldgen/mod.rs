@@ -0,0 +1,205 @@
+use std::error::Error;
+use std::fmt;
+
+use macroquad::math::{ivec2, IVec2, UVec2};
+use serde::{Deserialize, Serialize};
+
+use crate::entity::tile_map::Chunk;
+use crate::position::ChunkPos;
+use crate::tile::TileId;
+
+pub use biome::Biome;
+pub use terrain::DefaultGenerator;
+
+mod biome;
+mod caves;
+mod ores;
+mod structures;
+mod terrain;
+
+/// The tile row the ground is at (or rolls around) in every built-in generator.
+/// Row 8 of chunk (0, 0), where the very first flat world had it.
+pub const SURFACE_ROW: i32 = 8;
+
+/// Fills in new [`Chunk`]s for a [`crate::entity::tile_map::ChunkMap`].
+///
+/// A map saves its generator as [`WorldGenerator::name`] and [`WorldGenerator::params`],
+/// and [`from_saved`] turns those back into a generator, so new ones need adding there.
+pub trait WorldGenerator: fmt::Debug + Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Everything needed to rebuild this generator, as RON.
+    fn params(&self) -> String;
+
+    /// Chunks have to line up with their neighbours whatever order they're generated in,
+    /// so this can't depend on anything but `chunk` and the generator itself.
+    fn generate_chunk(&self, chunk: ChunkPos, chunk_size: UVec2) -> Chunk;
+
+    /// The row of the topmost solid tile in column `x` as generated, in world tiles.
+    fn surface_height(&self, x: i32) -> i32;
+
+    /// The biome column `x` is in. Generators without biomes are all plains.
+    fn biome_at(&self, _x: i32) -> Biome {
+        Biome::Plains
+    }
+}
+
+/// Rebuilds a generator saved as `name` and `params`.
+pub fn from_saved(name: &str, params: &str) -> Result<Box<dyn WorldGenerator>, Box<dyn Error>> {
+    Ok(match name {
+        "default" => Box::new(ron::from_str::<DefaultGenerator>(params)?),
+        "flat" => Box::new(ron::from_str::<FlatGenerator>(params)?),
+        "empty" => Box::new(ron::from_str::<EmptyGenerator>(params)?),
+        _ => return Err(format!("unknown world generator {name:?}").into()),
+    })
+}
+
+/// Flat ground of one tile, for creative worlds and tests that need to know where the floor is.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct FlatGenerator {
+    /// The row the ground starts at, in world tiles.
+    pub surface: i32,
+    pub tile: TileId,
+}
+
+impl Default for FlatGenerator {
+    fn default() -> Self {
+        FlatGenerator {
+            surface: SURFACE_ROW,
+            tile: TileId::Dirt,
+        }
+    }
+}
+
+impl WorldGenerator for FlatGenerator {
+    fn name(&self) -> &'static str {
+        "flat"
+    }
+
+    fn params(&self) -> String {
+        ron::to_string(self).expect("Serde RON failure")
+    }
+
+    fn generate_chunk(&self, chunk: ChunkPos, chunk_size: UVec2) -> Chunk {
+        fill_chunk(chunk, chunk_size, |tile| {
+            if tile.y >= self.surface { self.tile } else { TileId::Air }
+        })
+    }
+
+    fn surface_height(&self, _x: i32) -> i32 {
+        self.surface
+    }
+}
+
+/// Nothing but air. There's no ground to spawn on, so the player falls from where it would be.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct EmptyGenerator;
+
+impl WorldGenerator for EmptyGenerator {
+    fn name(&self) -> &'static str {
+        "empty"
+    }
+
+    fn params(&self) -> String {
+        ron::to_string(self).expect("Serde RON failure")
+    }
+
+    fn generate_chunk(&self, chunk: ChunkPos, chunk_size: UVec2) -> Chunk {
+        fill_chunk(chunk, chunk_size, |_| TileId::Air)
+    }
+
+    fn surface_height(&self, _x: i32) -> i32 {
+        SURFACE_ROW
+    }
+}
+
+/// Builds `chunk` out of `tile_at`, which is given positions in world tiles.
+pub fn fill_chunk(chunk: ChunkPos, chunk_size: UVec2, tile_at: impl Fn(IVec2) -> TileId) -> Chunk {
+    let origin = chunk.0 * chunk_size.as_ivec2();
+
+    Chunk::from_fn(chunk_size, |point| tile_at(origin + ivec2(point.x as i32, point.y as i32)))
+}
+
+/// Saves an `Arc<dyn WorldGenerator>` as its name and parameters, for `#[serde(with)]`.
+pub mod saved {
+    use std::sync::Arc;
+
+    use serde::{de::Error as _, Deserialize, Deserializer, Serialize, Serializer};
+
+    use super::WorldGenerator;
+
+    pub fn serialize<S: Serializer>(generator: &Arc<dyn WorldGenerator>, serializer: S) -> Result<S::Ok, S::Error> {
+        (generator.name(), generator.params()).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Arc<dyn WorldGenerator>, D::Error> {
+        let (name, params) = <(String, String)>::deserialize(deserializer)?;
+        super::from_saved(&name, &params)
+            .map(Arc::from)
+            .map_err(D::Error::custom)
+    }
+}
+
+/// Scrambles `seed` and `x` into a number that looks random, for one-off choices like where a tree goes.
+/// The same inputs always give the same number.
+pub fn hash(seed: u32, x: i32) -> u32 {
+    let mut h = (seed as u64) << 32 | x as u32 as u64;
+    // splitmix64's finaliser
+    h = (h ^ (h >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    h = (h ^ (h >> 27)).wrapping_mul(0x94d049bb133111eb);
+    (h ^ (h >> 31)) as u32
+}
+
+/// Seeds are kept under this. `noise` adds to seeds without wrapping, for an `Fbm`'s octaves,
+/// which panics on the seeds right at the top.
+const MAX_SEED: u32 = 0x7FFF_FFFF;
+
+/// The seed for one layer of a world's noise. Seeds next to each other can't be used for that,
+/// an `Fbm` seeds its octaves with the ones after its own, so layers would end up sharing noise.
+pub fn layer_seed(seed: u32, layer: i32) -> u32 {
+    hash(seed, layer) & MAX_SEED
+}
+
+/// A seed for a new world, different every time.
+pub fn random_seed() -> u32 {
+    let now = macroquad::miniquad::date::now();
+    ((now.fract() * u32::MAX as f64) as u32 ^ now as u32) & MAX_SEED
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generators_rebuild_from_name_and_params() -> Result<(), Box<dyn Error>> {
+        let generators: [Box<dyn WorldGenerator>; 3] = [
+            Box::new(DefaultGenerator::new(42)),
+            Box::new(FlatGenerator { surface: -3, tile: TileId::WoodPlanks }),
+            Box::new(EmptyGenerator),
+        ];
+        let size = UVec2::new(16, 14);
+
+        for generator in generators {
+            let rebuilt = from_saved(generator.name(), &generator.params())?;
+            assert_eq!(rebuilt.params(), generator.params());
+            assert_eq!(
+                rebuilt.generate_chunk(ChunkPos(ivec2(2, 0)), size),
+                generator.generate_chunk(ChunkPos(ivec2(2, 0)), size),
+            );
+        }
+        assert!(from_saved("moon", "()").is_err());
+        Ok(())
+    }
+    #[test]
+    fn layers_dont_share_noise() {
+        for seed in [0, 1, 7, 99, u32::MAX] {
+            // An `Fbm` uses up to 16 seeds from its own on
+            let seeds = (0..8).map(|layer| layer_seed(seed, layer)).collect::<Vec<_>>();
+            for (i, a) in seeds.iter().enumerate() {
+                for b in &seeds[i + 1..] {
+                    assert!(a.abs_diff(*b) >= 16, "seed {seed} has layers {a} and {b}");
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,66 @@
+use macroquad::math::IVec2;
+use noise::{NoiseFn, Perlin};
+
+use crate::tile::TileId;
+
+use super::layer_seed;
+
+/// A kind of ore and where it turns up.
+#[derive(Clone, Copy, Debug)]
+pub struct Vein {
+    pub tile: TileId,
+    /// Tiles below the surface before this ore appears at all.
+    pub min_depth: i32,
+    /// Noise above this is ore, at `min_depth`. Lower is more common.
+    pub threshold: f64,
+    /// Roughly how many tiles across a vein is.
+    pub size: f64,
+}
+
+/// Ores get this much more common per tile of depth past their `min_depth`...
+const COMMONER_PER_TILE: f64 = 0.002;
+/// ...until they're this common.
+const MIN_THRESHOLD: f64 = 0.45;
+
+/// Every ore that generates, each gets its own noise.
+const ORES: [TileId; 3] = [TileId::GenericOre, TileId::IronOre, TileId::GoldOre];
+
+/// The usual ores, for biomes without anything special going on.
+pub const VEINS: [Vein; 3] = [
+    Vein { tile: TileId::GenericOre, min_depth: 3, threshold: 0.6, size: 6.0 },
+    Vein { tile: TileId::IronOre, min_depth: 20, threshold: 0.65, size: 5.0 },
+    Vein { tile: TileId::GoldOre, min_depth: 50, threshold: 0.72, size: 4.0 },
+];
+
+/// Where ore replaces stone. Each kind of ore has its own noise, so veins of different ores overlap freely,
+/// and a vein carries on under a biome border if both biomes have that ore.
+#[derive(Clone, Debug)]
+pub struct OreVeins {
+    noise: Vec<Perlin>,
+}
+
+impl OreVeins {
+    pub fn new(seed: u32) -> Self {
+        OreVeins {
+            noise: (0..ORES.len() as i32).map(|i| Perlin::new(layer_seed(seed, i))).collect(),
+        }
+    }
+
+    /// The ore at `tile`, `depth` tiles below the surface, if `veins` has any there.
+    pub fn ore_at(&self, tile: IVec2, depth: i32, veins: &[Vein]) -> Option<TileId> {
+        let (x, y) = (tile.x as f64 + 0.5, tile.y as f64 + 0.5);
+
+        // Rarer ores are further down the list, and win where veins cross
+        veins.iter().rev()
+            .find(|vein| {
+                let threshold = (vein.threshold - (depth - vein.min_depth) as f64 * COMMONER_PER_TILE).max(MIN_THRESHOLD);
+                depth >= vein.min_depth && self.noise(vein.tile).get([x / vein.size, y / vein.size]) > threshold
+            })
+            .map(|vein| vein.tile)
+    }
+
+    fn noise(&self, ore: TileId) -> &Perlin {
+        let i = ORES.iter().position(|&tile| tile == ore).expect("vein of a tile that isn't an ore");
+        &self.noise[i]
+    }
+}
@@ -0,0 +1,158 @@
+use std::collections::HashMap;
+use std::sync::LazyLock;
+
+use macroquad::math::{ivec2, uvec2, IVec2};
+use serde::Deserialize;
+
+use crate::grid::Grid;
+use crate::tile::TileId;
+
+use super::biome::Biome;
+use super::hash;
+
+/// Structures only go where the ground at either end is within this many tiles of the ground at the anchor,
+/// so they aren't buried in hillsides.
+const MAX_SLOPE: i32 = 2;
+
+/// Every structure that generates, one for each file in `assets/structures` (listed by `build.rs`).
+pub static STRUCTURES: LazyLock<Vec<Structure>> = LazyLock::new(|| {
+    include!(concat!(env!("OUT_DIR"), "/structures.rs"))
+    .into_iter()
+    .map(|file| ron::from_str(file).expect("Bad structure file"))
+    .collect()
+});
+
+/// A hand-built arrangement of tiles stamped over the terrain, like a cabin or a mine shaft.
+///
+/// Each structure gets one spot in every `spacing` columns, so copies of the same
+/// structure never overlap. Where it goes depends on nothing but the seed and the terrain at its
+/// spot, so every chunk it covers can work out its own part of it, whenever that chunk is generated.
+#[derive(Debug, Deserialize)]
+#[serde(try_from = "StructureFile")]
+pub struct Structure {
+    pub name: String,
+    biomes: Vec<Biome>,
+    spacing: i32,
+    chance: u32,
+    anchor: IVec2,
+    /// `None` leaves the generated tile alone.
+    tiles: Grid<Option<TileId>>,
+}
+
+/// A structure as written in its file: rows of characters, with a legend saying which tile each is.
+/// Spaces aren't part of the structure.
+#[derive(Deserialize)]
+#[serde(rename = "Structure")]
+struct StructureFile {
+    name: String,
+    /// Which biomes it can turn up in, going by the biome at its anchor.
+    biomes: Vec<Biome>,
+    /// How many columns to each spot it could go in. Has to be wider than the structure.
+    spacing: i32,
+    /// Out of 100, how likely each spot is to actually have one.
+    chance: u32,
+    legend: HashMap<char, TileId>,
+    /// The tile in `tiles` that sits on the ground, as (column, row).
+    anchor: (i32, i32),
+    tiles: Vec<String>,
+}
+
+impl TryFrom<StructureFile> for Structure {
+    type Error = String;
+
+    fn try_from(file: StructureFile) -> Result<Self, Self::Error> {
+        let name = file.name;
+        let width = file.tiles.first().map_or(0, |row| row.chars().count());
+        let height = file.tiles.len();
+
+        if width == 0 || file.tiles.iter().any(|row| row.chars().count() != width) {
+            return Err(format!("{name}: rows have to be the same length, and not empty"));
+        }
+        if file.spacing <= width as i32 {
+            return Err(format!("{name}: spacing has to be wider than the structure"));
+        }
+        let anchor = ivec2(file.anchor.0, file.anchor.1);
+        if anchor.x < 0 || anchor.y < 0 || anchor.x >= width as i32 || anchor.y >= height as i32 {
+            return Err(format!("{name}: anchor is outside the structure"));
+        }
+
+        let mut tiles = Vec::with_capacity(width * height);
+        for c in file.tiles.iter().flat_map(|row| row.chars()) {
+            tiles.push(match c {
+                ' ' => None,
+                c => Some(*file.legend.get(&c).ok_or_else(|| format!("{name}: {c:?} isn't in the legend"))?),
+            });
+        }
+
+        Ok(Structure {
+            name,
+            biomes: file.biomes,
+            spacing: file.spacing,
+            chance: file.chance,
+            anchor,
+            tiles: Grid { array: tiles, width, height },
+        })
+    }
+}
+
+impl Structure {
+    pub fn size(&self) -> IVec2 {
+        ivec2(self.tiles.width as i32, self.tiles.height as i32)
+    }
+
+    /// Where the copy in spot number `spot` goes, as its top left corner in world tiles, if there is one.
+    /// `ground` and `biome` say what the terrain is like in a column.
+    pub fn place(&self, seed: u32, spot: i32, ground: impl Fn(i32) -> i32, biome: impl Fn(i32) -> Biome) -> Option<IVec2> {
+        // Salted by name, so structures don't all pick the same columns
+        let salt = self.name.bytes().fold(0u32, |h, b| h.wrapping_mul(31).wrapping_add(b as u32));
+        let roll = hash(seed ^ salt, spot);
+
+        let free_columns = (self.spacing - self.size().x + 1) as u32;
+        let left = spot * self.spacing + (roll % free_columns) as i32;
+        let anchor_x = left + self.anchor.x;
+
+        let grows = (roll >> 16) % 100 < self.chance;
+        if !grows || !self.biomes.contains(&biome(anchor_x)) {
+            return None;
+        }
+
+        let floor = ground(anchor_x);
+        let right = left + self.size().x - 1;
+        let flat = [left, right].into_iter().all(|x| (ground(x) - floor).abs() <= MAX_SLOPE);
+        flat.then(|| ivec2(left, floor - self.anchor.y))
+    }
+
+    /// The spots whose copies could reach into columns `min_x..max_x`.
+    pub fn spots_between(&self, min_x: i32, max_x: i32) -> impl Iterator<Item = i32> {
+        (min_x - self.size().x).div_euclid(self.spacing)..=(max_x - 1).div_euclid(self.spacing)
+    }
+
+    /// The tile this structure puts at `tile`, if it's placed at `origin`.
+    pub fn tile_at(&self, origin: IVec2, tile: IVec2) -> Option<TileId> {
+        let offset = tile - origin;
+        if offset.cmplt(IVec2::ZERO).any() || offset.cmpge(self.size()).any() {
+            return None;
+        }
+        self.tiles[uvec2(offset.x as u32, offset.y as u32)]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn structure_files_load() {
+        assert!(STRUCTURES.iter().any(|structure| structure.name == "cabin"));
+        assert!(STRUCTURES.iter().any(|structure| structure.name == "mine_shaft"));
+        let files = std::fs::read_dir(concat!(env!("CARGO_MANIFEST_DIR"), "/assets/structures")).unwrap()
+            .filter(|entry| entry.as_ref().unwrap().path().extension().is_some_and(|ext| ext == "ron"))
+            .count();
+        assert_eq!(STRUCTURES.len(), files);
+
+        let ragged = r#"Structure(name: "bad", biomes: [], spacing: 10, chance: 1, legend: {'P': WoodPlanks}, anchor: (0, 0), tiles: ["PP", "P"])"#;
+        assert!(ron::from_str::<Structure>(ragged).is_err());
+        let unknown = r#"Structure(name: "bad", biomes: [], spacing: 10, chance: 1, legend: {}, anchor: (0, 0), tiles: ["X"])"#;
+        assert!(ron::from_str::<Structure>(unknown).is_err());
+    }
+}
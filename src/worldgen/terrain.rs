@@ -0,0 +1,300 @@
+use macroquad::math::{IVec2, UVec2};
+use noise::{Fbm, MultiFractal, NoiseFn, Perlin};
+use serde::{Deserialize, Serialize};
+
+use crate::entity::tile_map::Chunk;
+use crate::position::ChunkPos;
+use crate::tile::TileId;
+
+use super::biome::{Biome, Biomes};
+use super::caves::Caves;
+use super::ores::OreVeins;
+use super::structures::{Structure, STRUCTURES};
+use super::{fill_chunk, hash, layer_seed, WorldGenerator, SURFACE_ROW};
+
+/// Roughly how many tiles from one hilltop to the next.
+const HILL_WIDTH: f64 = 48.0;
+/// How much the soil depth wanders either side of the biome's.
+const SOIL_VARIATION: f64 = 2.0;
+/// Trees get at most one spot in every this many columns, so they never touch.
+const TREE_SPACING: i32 = 6;
+/// Out of 100, how likely each spot is to actually have a tree.
+const TREE_CHANCE: u32 = 60;
+const TREE_MIN_HEIGHT: i32 = 3;
+const TREE_MAX_HEIGHT: i32 = 5;
+
+/// Which [`layer_seed`] each part of the terrain is made from.
+const SURFACE_LAYER: i32 = 0;
+const DIRT_LAYER: i32 = 1;
+const CAVE_LAYER: i32 = 2;
+const ORE_LAYER: i32 = 3;
+const BIOME_LAYER: i32 = 4;
+const TREE_LAYER: i32 = 5;
+
+/// Noise-based terrain for one seed. Every tile is a function of the seed and its position
+/// alone, so chunks line up however and whenever they're generated.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(into = "DefaultParams", from = "DefaultParams")]
+pub struct DefaultGenerator {
+    seed: u32,
+    surface: Fbm<Perlin>,
+    dirt: Perlin,
+    caves: Caves,
+    ores: OreVeins,
+    biomes: Biomes,
+}
+
+/// All a [`DefaultGenerator`] needs saving, the noise is rebuilt from it.
+#[derive(Serialize, Deserialize)]
+struct DefaultParams {
+    seed: u32,
+}
+
+impl From<DefaultParams> for DefaultGenerator {
+    fn from(params: DefaultParams) -> Self {
+        DefaultGenerator::new(params.seed)
+    }
+}
+
+impl From<DefaultGenerator> for DefaultParams {
+    fn from(generator: DefaultGenerator) -> Self {
+        DefaultParams { seed: generator.seed }
+    }
+}
+
+impl DefaultGenerator {
+    pub fn new(seed: u32) -> Self {
+        DefaultGenerator {
+            seed,
+            surface: Fbm::<Perlin>::new(layer_seed(seed, SURFACE_LAYER))
+                .set_octaves(4)
+                .set_frequency(1.0 / HILL_WIDTH),
+            dirt: Perlin::new(layer_seed(seed, DIRT_LAYER)),
+            caves: Caves::new(layer_seed(seed, CAVE_LAYER)),
+            ores: OreVeins::new(layer_seed(seed, ORE_LAYER)),
+            biomes: Biomes::new(layer_seed(seed, BIOME_LAYER)),
+        }
+    }
+
+    pub fn seed(&self) -> u32 {
+        self.seed
+    }
+
+    pub fn tile_at(&self, tile: IVec2) -> TileId {
+        let structures = self.structures_in(tile, tile + IVec2::ONE);
+        self.tile_with(&structures, tile)
+    }
+
+    /// The tile at `tile` with `structures` stamped over the terrain. The first structure to cover it wins.
+    fn tile_with(&self, structures: &[(&Structure, IVec2)], tile: IVec2) -> TileId {
+        structures.iter()
+            .find_map(|(structure, origin)| structure.tile_at(*origin, tile))
+            .unwrap_or_else(|| self.terrain_at(tile))
+    }
+
+    /// Every structure that reaches into the tiles from `min` up to `max`, with where it goes.
+    pub fn structures_in(&self, min: IVec2, max: IVec2) -> Vec<(&'static Structure, IVec2)> {
+        STRUCTURES.iter()
+            .flat_map(|structure| structure.spots_between(min.x, max.x).map(move |spot| (structure, spot)))
+            .filter_map(|(structure, spot)| {
+                let origin = structure.place(self.seed, spot, |x| self.ground_height(x), |x| self.biomes.biome_at(x))?;
+                let end = origin + structure.size();
+                (origin.cmplt(max).all() && end.cmpgt(min).all()).then_some((structure, origin))
+            })
+            .collect()
+    }
+
+    /// The tile at `tile` before any structures are stamped on.
+    fn terrain_at(&self, tile: IVec2) -> TileId {
+        let ground = self.ground_height(tile.x);
+        let biome = self.biomes.biome_at(tile.x);
+        let palette = biome.palette();
+        let soil_depth = palette.soil_depth + self.dirt.get([tile.x as f64 * 0.1, 0.5]) * SOIL_VARIATION;
+
+        let depth = tile.y - ground;
+
+        if depth < 0 {
+            match self.tree_height(tile.x) {
+                Some(height) if depth >= -height => TileId::WoodLog,
+                _ => TileId::Air,
+            }
+        } else if self.caves.is_cave(tile, depth, biome.caverns()) {
+            TileId::Air
+        } else if depth == 0 {
+            palette.top
+        } else if (depth as f64) < soil_depth {
+            palette.soil
+        } else {
+            self.ores.ore_at(tile, depth, biome.ores()).unwrap_or(TileId::Stone)
+        }
+    }
+
+    /// The row of the top of the ground in column `x`, not counting trees.
+    pub fn ground_height(&self, x: i32) -> i32 {
+        // Perlin noise is zero on whole numbers, so sample between them
+        let hills = self.surface.get([x as f64, 0.5]);
+        let offset = self.biomes.blend(x, Biome::ground_offset);
+        let height = self.biomes.blend(x, Biome::hill_height);
+        (SURFACE_ROW as f64 + offset + hills * height).round() as i32
+    }
+
+    /// How tall the tree growing in column `x` is, if there is one.
+    /// Trees are a single column of logs, so they never reach into another column's chunk.
+    pub fn tree_height(&self, x: i32) -> Option<i32> {
+        let spot = x.div_euclid(TREE_SPACING);
+        let roll = hash(layer_seed(self.seed, TREE_LAYER), spot);
+
+        // Leave the last column of each spot empty, so neighbouring trees always have a gap
+        let column = spot * TREE_SPACING + (roll % (TREE_SPACING - 1) as u32) as i32;
+        let grows = (roll >> 8) % 100 < TREE_CHANCE;
+        let height = TREE_MIN_HEIGHT + ((roll >> 16) % (TREE_MAX_HEIGHT - TREE_MIN_HEIGHT + 1) as u32) as i32;
+
+        (x == column && grows && self.biomes.biome_at(x).has_trees()).then_some(height)
+    }
+}
+
+impl WorldGenerator for DefaultGenerator {
+    fn name(&self) -> &'static str {
+        "default"
+    }
+
+    fn params(&self) -> String {
+        ron::to_string(self).expect("Serde RON failure")
+    }
+
+    fn generate_chunk(&self, chunk: ChunkPos, chunk_size: UVec2) -> Chunk {
+        // Structures big enough to cover more than one chunk get worked out again by each of them
+        let origin = chunk.0 * chunk_size.as_ivec2();
+        let structures = self.structures_in(origin, origin + chunk_size.as_ivec2());
+        fill_chunk(chunk, chunk_size, |tile| self.tile_with(&structures, tile))
+    }
+
+    fn surface_height(&self, x: i32) -> i32 {
+        self.ground_height(x) - self.tree_height(x).unwrap_or(0)
+    }
+
+    fn biome_at(&self, x: i32) -> Biome {
+        self.biomes.biome_at(x)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use macroquad::math::ivec2;
+    use super::*;
+
+    #[test]
+    fn chunks_line_up_across_borders() {
+        let generator = DefaultGenerator::new(1234);
+        let size = UVec2::new(16, 14);
+        let left = generator.generate_chunk(ChunkPos(ivec2(-1, 0)), size);
+        let right = generator.generate_chunk(ChunkPos(ivec2(0, 0)), size);
+
+        // Each chunk's edge column is the same as asking the generator directly
+        for y in 0..14 {
+            assert_eq!(left.get(UVec2::new(15, y)), Some(generator.tile_at(ivec2(-1, y as i32))));
+            assert_eq!(right.get(UVec2::new(0, y)), Some(generator.tile_at(ivec2(0, y as i32))));
+        }
+        // And the ground doesn't jump more than a couple of tiles between them
+        assert!((generator.ground_height(-1) - generator.ground_height(0)).abs() <= 2);
+    }
+
+    #[test]
+    fn same_seed_same_world() {
+        let size = UVec2::new(16, 14);
+        let a = DefaultGenerator::new(99).generate_chunk(ChunkPos(ivec2(3, 0)), size);
+        let b = DefaultGenerator::new(99).generate_chunk(ChunkPos(ivec2(3, 0)), size);
+        let c = DefaultGenerator::new(100).generate_chunk(ChunkPos(ivec2(3, 0)), size);
+
+        assert_eq!(a, b);
+        assert_ne!(a, c);
+
+        // Seeds at the very top don't overflow building the noise
+        DefaultGenerator::new(u32::MAX).generate_chunk(ChunkPos(ivec2(0, 4)), size);
+    }
+
+    #[test]
+    fn underground_has_caves_and_ores_by_depth() {
+        let generator = DefaultGenerator::new(7);
+        let mut found = std::collections::HashSet::<TileId>::new();
+
+        for x in -200..200 {
+            let ground = generator.ground_height(x);
+            let biome = generator.biome_at(x);
+            for depth in 0..120 {
+                let tile = generator.terrain_at(ivec2(x, ground + depth));
+                found.insert(tile);
+
+                // The surface itself is never hollowed out, and ores stay as deep as their biome says
+                if tile == TileId::Air {
+                    assert!(depth >= 6, "cave {depth} tiles down at x = {x}");
+                }
+                if let Some(vein) = biome.ores().iter().find(|vein| vein.tile == tile) {
+                    assert!(depth >= vein.min_depth, "{tile:?} {depth} tiles down in {biome:?}");
+                }
+            }
+        }
+
+        for tile in [TileId::Air, TileId::GenericOre, TileId::IronOre, TileId::GoldOre] {
+            assert!(found.contains(&tile), "no {tile:?} generated");
+        }
+    }
+
+    #[test]
+    fn biomes_shape_the_ground() {
+        let generator = DefaultGenerator::new(7);
+        let mut biomes = std::collections::HashSet::new();
+
+        for x in -2000..2000 {
+            let biome = generator.biome_at(x);
+            biomes.insert(biome);
+
+            // Biome borders blend instead of making cliffs
+            assert!((generator.ground_height(x) - generator.ground_height(x + 1)).abs() <= 3, "cliff at x = {x}");
+
+            let ground = generator.ground_height(x);
+            assert_eq!(generator.terrain_at(ivec2(x, ground)), biome.palette().top);
+            match generator.tree_height(x) {
+                Some(height) => {
+                    assert_eq!(biome, Biome::Forest);
+                    assert_eq!(generator.terrain_at(ivec2(x, ground - height)), TileId::WoodLog);
+                    assert_eq!(generator.terrain_at(ivec2(x, ground - height - 1)), TileId::Air);
+                    assert_eq!(generator.surface_height(x), ground - height);
+                }
+                None => assert_eq!(generator.terrain_at(ivec2(x, ground - 1)), TileId::Air),
+            }
+        }
+
+        assert_eq!(biomes.len(), 4, "only found {biomes:?}");
+    }
+
+    #[test]
+    fn structures_stamp_across_chunks() {
+        let generator = DefaultGenerator::new(7);
+        let size = UVec2::new(16, 14);
+        let (structure, origin) = generator.structures_in(ivec2(-5000, -100), ivec2(5000, 100))
+            .into_iter()
+            .find(|(structure, _)| structure.name == "mine_shaft")
+            .expect("no mine shafts");
+
+        let first = ChunkPos(origin.div_euclid(size.as_ivec2()));
+        let last = ChunkPos((origin + structure.size() - 1).div_euclid(size.as_ivec2()));
+        assert_ne!(first, last, "mine shaft fits in one chunk");
+
+        // Every chunk it covers has its part, whichever one is generated first
+        for chunk_y in (first.0.y..=last.0.y).rev() {
+            for chunk_x in (first.0.x..=last.0.x).rev() {
+                let chunk_pos = ChunkPos(ivec2(chunk_x, chunk_y));
+                let chunk = DefaultGenerator::new(7).generate_chunk(chunk_pos, size);
+                let chunk_origin = chunk_pos.0 * size.as_ivec2();
+
+                for (point, tile) in chunk.tiles() {
+                    let pos = chunk_origin + point.as_ivec2();
+                    if let Some(expected) = structure.tile_at(origin, pos) {
+                        assert_eq!(tile, expected, "at {pos}");
+                    }
+                }
+            }
+        }
+    }
+}
@@ -70,6 +70,7 @@ HTML=$(
 		<html lang="en">
 		<head>
 		    <meta charset="utf-8">
+		    <meta name="viewport" content="width=device-width, initial-scale=1, user-scalable=no">
 		    <title>${PROJECT_NAME}</title>
 		    <style>
 		        html,
@@ -82,6 +83,7 @@ HTML=$(
 		            overflow: hidden;
 		            position: absolute;
 		            z-index: 0;
+		            touch-action: none;
 		        }
 				.popover {
 				    z-index: 10;
@@ -127,8 +129,21 @@ HTML=$(
                     e.preventDefault();
                 }, false);
                 
+                // A hidden tab gets no more frames, so save while there's still the chance
+                function suspend_game() {
+                    if (typeof wasm_exports !== "undefined") {
+                        wasm_exports.suspend_game();
+                    }
+                }
+                document.addEventListener("visibilitychange", function () {
+                    if (document.visibilityState === "hidden") {
+                        suspend_game();
+                    }
+                });
+                window.addEventListener("pagehide", suspend_game);
+                
                 function show_controls() {
-                    alert("W/S: left/right.\nSpace: jump.\nSpace x2: Fly for some time\nLeft click: remove block\nRight click: place block\nX: Go to spawn");
+                    alert("A/D: left/right.\nSpace: jump.\nSpace x2: Fly for some time\nLeft click: remove block\nRight click: place block\nQ/E or scroll: change block\nX: Go to spawn\nEsc: pause\n\nTouch: joystick and jump button on screen, tap to mine, hold to place, tap the hotbar to change block, pause button in the corner");
                 }
                 
                 function go_away() {